@@ -12,22 +12,55 @@
 
 use crate::prelude::*;
 use afbv4::prelude::*;
+use charging::prelude::*;
 use typesv4::prelude::*;
 
-pub struct BindingCfg {
+// one charging socket, iec/slac/energy meter apis are per connector
+pub struct ConnectorCfg {
     pub iec_api: &'static str,
     pub slac_api: Option<&'static str>,
+    pub energy_api: &'static str,
+    pub evse_id: u32,
+    pub connector_id: u32,
+    pub lock: Option<CableLockConfig>,
+    pub relay: Option<RelayConfig>,
+    pub phase: Option<PhaseConfig>,
+}
+
+pub struct BindingCfg {
+    pub connectors: Vec<ConnectorCfg>,
+    pub budget_imax: Option<u32>,
+    pub slac_retry: u32,
     pub auth_api: &'static str,
     pub engy_api: &'static str,
     pub ocpp_api: Option<&'static str>,
+    pub ocpp_version: OcppVersion,
     pub tic: u32,
     pub limit: u32,
     pub basic_charging_enabled: bool,
+    pub fallback: FallbackPolicy,
+    pub auth_timeout: u32,
+    pub auth_hold: u32,
+    pub iso_vmax: u32,
+    pub v2g: Option<V2gConfig>,
+    pub solar: Option<SolarConfig>,
+    pub tariff: Option<TariffConfig>,
+    pub thermal: Option<ThermalConfig>,
+    pub safety: Option<SafetyConfig>,
+    pub meter: Option<MeterConfig>,
+    pub ocmf: Option<&'static str>,
+    pub vendor_id: Option<&'static str>,
+    pub pnc_trust_store: Option<&'static str>,
+    pub pnc_local_list: Option<&'static str>,
+    pub contract_imax: u32,
+    pub contract_pmax: u32,
 }
 
 pub struct ApiUserData {
-    pub iec_api: &'static str,
-    pub slac_api: Option<&'static str>,
+    pub connectors: Vec<(&'static str, Option<&'static str>, &'static str)>,
+    pub solar: Option<(&'static str, &'static str)>,
+    pub thermal: Option<(&'static str, &'static str)>,
+    pub meter: bool,
     pub engy_api: &'static str,
     pub ocpp_api: Option<&'static str>,
 }
@@ -35,15 +68,29 @@ pub struct ApiUserData {
 impl AfbApiControls for ApiUserData {
     // the API is created and ready. At this level user may subcall api(s) declare as dependencies
     fn start(&mut self, api: &AfbApi) -> Result<(), AfbError> {
-        AfbSubCall::call_sync(api, self.iec_api, "subscribe", true)?;
-        if self.slac_api.is_some() {
-            AfbSubCall::call_sync(api, self.slac_api.unwrap(), "subscribe", true)?;
+        for (iec_api, slac_api, energy_api) in &self.connectors {
+            AfbSubCall::call_sync(api, iec_api, "subscribe", true)?;
+            if let Some(slac_api) = slac_api {
+                AfbSubCall::call_sync(api, slac_api, "subscribe", true)?;
+            }
+            // each connector only listens to its own meter
+            AfbSubCall::call_sync(api, energy_api, "iover", EnergyAction::SUBSCRIBE)?;
+            AfbSubCall::call_sync(api, energy_api, "energy", EnergyAction::SUBSCRIBE)?;
+            if self.meter {
+                AfbSubCall::call_sync(api, energy_api, "current", EnergyAction::SUBSCRIBE)?;
+            }
         }
         if self.ocpp_api.is_some() {
             AfbSubCall::call_sync(api, self.ocpp_api.unwrap(), "subscribe", true)?;
         }
+        // site available current comes from the main energy api
         AfbSubCall::call_sync(api, self.engy_api, "iavail", EnergyAction::SUBSCRIBE)?;
-        AfbSubCall::call_sync(api, self.engy_api, "iover", EnergyAction::SUBSCRIBE)?;
+        if let Some((solar_api, solar_verb)) = self.solar {
+            AfbSubCall::call_sync(api, solar_api, solar_verb, EnergyAction::SUBSCRIBE)?;
+        }
+        if let Some((thermal_api, thermal_verb)) = self.thermal {
+            AfbSubCall::call_sync(api, thermal_api, thermal_verb, EnergyAction::SUBSCRIBE)?;
+        }
         Ok(())
     }
 
@@ -53,6 +100,117 @@ impl AfbApiControls for ApiUserData {
     }
 }
 
+// cable lock is optional and configured per connector
+fn lock_config(jconf: &JsoncObj) -> Result<Option<CableLockConfig>, AfbError> {
+    let lock = match jconf.optional::<&'static str>("lock_api")? {
+        Some(value) => Some(CableLockConfig {
+            api: value,
+            verb: jconf.default::<&'static str>("lock_verb", "gpio/lock-motor")?,
+            status_verb: jconf.default::<&'static str>("lock_status_verb", "gpio/lock-status")?,
+            mask: jconf.default::<u32>("lock_mask", 1)?,
+        }),
+        None => None,
+    };
+    Ok(lock)
+}
+
+// contactor supervision is optional and configured per connector
+fn relay_config(jconf: &JsoncObj) -> Result<Option<RelayConfig>, AfbError> {
+    let relay = match jconf.optional::<&'static str>("relay_api")? {
+        Some(value) => Some(RelayConfig {
+            api: value,
+            status_verb: jconf.default::<&'static str>("relay_status_verb", "gpio/relay-status")?,
+            mask: jconf.default::<u32>("relay_mask", 1)?,
+            delay: jconf.default::<u32>("relay_delay", 500)?,
+            current: jconf.default::<i32>("relay_current", 500)?,
+        }),
+        None => None,
+    };
+    Ok(relay)
+}
+
+// 1p/3p switching relay is optional and configured per connector
+fn phase_config(jconf: &JsoncObj) -> Result<Option<PhaseConfig>, AfbError> {
+    let phase = match jconf.optional::<&'static str>("phase_api")? {
+        Some(value) => Some(PhaseConfig {
+            api: value,
+            verb: jconf.default::<&'static str>("phase_verb", "gpio/phase-relay")?,
+            pause: jconf.default::<u32>("phase_pause", 30000)?,
+            delay: jconf.default::<u32>("phase_delay", 300000)?,
+        }),
+        None => None,
+    };
+    Ok(phase)
+}
+
+// derating curve points must be sorted by increasing temperature
+fn thermal_config(jconf: &JsoncObj) -> Result<Option<ThermalConfig>, AfbError> {
+    let api = match jconf.optional::<&'static str>("thermal_api")? {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+
+    let mut curve: Vec<ThermalPoint> = Vec::new();
+    if let Some(jcurve) = jconf.optional::<JsoncObj>("thermal_curve")? {
+        for idx in 0..jcurve.count()? {
+            let jpoint = jcurve.index::<JsoncObj>(idx)?;
+            let point = ThermalPoint {
+                temperature: jpoint.get::<i32>("temperature")?,
+                imax: jpoint.get::<u32>("imax")?,
+            };
+            if let Some(previous) = curve.last() {
+                if point.temperature <= previous.temperature {
+                    return afb_error!(
+                        "binding-chmgr-config",
+                        "thermal_curve temperature:{} not increasing",
+                        point.temperature
+                    );
+                }
+            }
+            curve.push(point);
+        }
+    }
+
+    Ok(Some(ThermalConfig {
+        api,
+        verb: jconf.default::<&'static str>("thermal_verb", "temperature")?,
+        curve,
+        cutoff: jconf.default::<i32>("thermal_cutoff", 85)?,
+        hysteresis: jconf.default::<i32>("thermal_hysteresis", 10)?,
+    }))
+}
+
+// self-tests are enabled by listing them in safety_tests
+fn safety_config(jconf: &JsoncObj) -> Result<Option<SafetyConfig>, AfbError> {
+    let jtests = match jconf.optional::<JsoncObj>("safety_tests")? {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+
+    let mut tests = Vec::new();
+    for idx in 0..jtests.count()? {
+        let test = match jtests.index::<&'static str>(idx)? {
+            "rcd" => SafetyTest::Rcd,
+            "relayweld" => SafetyTest::RelayWeld,
+            "pe" => SafetyTest::Pe,
+            value => {
+                return afb_error!(
+                    "binding-chmgr-config",
+                    "invalid safety test:{} (rcd|relayweld|pe)",
+                    value
+                )
+            }
+        };
+        tests.push(test);
+    }
+
+    Ok(Some(SafetyConfig {
+        tests,
+        on_plugin: jconf.default::<bool>("safety_on_plugin", true)?,
+        period: jconf.default::<u32>("safety_period", 0)?,
+    }))
+}
+
 // Binding init callback started at binding load time before any API exist
 // -----------------------------------------
 pub fn binding_init(rootv4: AfbApiV4, jconf: JsoncObj) -> Result<&'static AfbApi, AfbError> {
@@ -90,40 +248,209 @@ pub fn binding_init(rootv4: AfbApiV4, jconf: JsoncObj) -> Result<&'static AfbApi
         64
     };
 
-    let iec_api = jconf.get::<&'static str>("iec_api")?;
-    let slac_api = jconf.optional::<&'static str>("slac_api")?;
     let auth_api = jconf.get::<&'static str>("auth_api")?;
     let engy_api = jconf.get::<&'static str>("energy_api")?;
     let ocpp_api = jconf.optional::<&'static str>("ocpp_api")?;
     let tic = jconf.default::<u32>("tic", 0)?;
+
+    let ocpp_version = match jconf.default::<&'static str>("ocpp_version", "1.6")? {
+        "1.6" => OcppVersion::V16,
+        "2.0.1" => OcppVersion::V201,
+        value => {
+            return afb_error!(
+                "binding-chmgr-config",
+                "invalid ocpp_version:{} (1.6|2.0.1)",
+                value
+            )
+        }
+    };
+
+    // without connectors array, iec/slac/lock keys describe a single connector
+    let mut connectors = Vec::new();
+    match jconf.optional::<JsoncObj>("connectors")? {
+        Some(jconnectors) => {
+            for idx in 0..jconnectors.count()? {
+                let jconnector = jconnectors.index::<JsoncObj>(idx)?;
+                connectors.push(ConnectorCfg {
+                    iec_api: jconnector.get::<&'static str>("iec_api")?,
+                    slac_api: jconnector.optional::<&'static str>("slac_api")?,
+                    energy_api: jconnector.default::<&'static str>("energy_api", engy_api)?,
+                    evse_id: jconnector.default::<u32>("evse_id", idx as u32 + 1)?,
+                    connector_id: jconnector.default::<u32>("connector_id", idx as u32 + 1)?,
+                    lock: lock_config(&jconnector)?,
+                    relay: relay_config(&jconnector)?,
+                    phase: phase_config(&jconnector)?,
+                });
+            }
+        }
+        None => connectors.push(ConnectorCfg {
+            iec_api: jconf.get::<&'static str>("iec_api")?,
+            slac_api: jconf.optional::<&'static str>("slac_api")?,
+            energy_api: engy_api,
+            evse_id: jconf.default::<u32>("evse_id", 1)?,
+            connector_id: jconf.default::<u32>("connector_id", 1)?,
+            lock: lock_config(&jconf)?,
+            relay: relay_config(&jconf)?,
+            phase: phase_config(&jconf)?,
+        }),
+    }
+    if connectors.is_empty() {
+        return afb_error!("binding-chmgr-config", "connectors array is empty");
+    }
+    // SLAC restarts need slac_api, connectors without it fall back on first UNMATCHED
+    let slac_retry = jconf.optional::<u32>("slac_retry")?;
+    if let Some(retry) = slac_retry {
+        if let Some(connector) = connectors
+            .iter()
+            .find(|connector| retry > 0 && connector.slac_api.is_none())
+        {
+            return afb_error!(
+                "binding-chmgr-config",
+                "connector:{} slac_retry:{} requires slac_api",
+                connector.connector_id,
+                retry
+            );
+        }
+    }
+    // session energy is reset on plug-in, a meter cannot be shared between connectors
+    for (idx, connector) in connectors.iter().enumerate() {
+        if connectors[..idx]
+            .iter()
+            .any(|previous| previous.energy_api == connector.energy_api)
+        {
+            return afb_error!(
+                "binding-chmgr-config",
+                "connector:{} energy_api:{} already used by another connector",
+                connector.connector_id,
+                connector.energy_api
+            );
+        }
+    }
+
+    let discharge_imax = jconf.optional::<u32>("discharge_imax")?;
+    let v2g = match discharge_imax {
+        Some(value) => Some(V2gConfig {
+            imax: value,
+            pmax: jconf.default::<u32>("discharge_pmax", 11)?,
+            soc_min: jconf.default::<u32>("discharge_soc_min", 20)?,
+        }),
+        None => None,
+    };
+
+    let solar_api = jconf.optional::<&'static str>("solar_api")?;
+    let solar = match solar_api {
+        Some(value) => Some(SolarConfig {
+            api: value,
+            verb: jconf.default::<&'static str>("solar_verb", "grid-power")?,
+            smoothing: jconf.default::<u32>("solar_smoothing", 20)?,
+            hybrid_imin: jconf.default::<u32>("solar_hybrid_imin", 6)?,
+            pause_delay: jconf.default::<u32>("solar_pause_delay", 300000)?,
+        }),
+        None => None,
+    };
+
+    let meter_stale = jconf.optional::<u32>("meter_stale")?;
+    let meter = match meter_stale {
+        Some(value) => Some(MeterConfig {
+            stale: value,
+            tolerance: jconf.default::<u32>("meter_tolerance", 2)?,
+            idle: jconf.default::<i32>("meter_idle", 500)?,
+        }),
+        None => None,
+    };
+
+    let offpeak_start = jconf.optional::<u32>("offpeak_start")?;
+    let tariff = match offpeak_start {
+        Some(value) => Some(TariffConfig {
+            offpeak_start: value,
+            offpeak_stop: jconf.get::<u32>("offpeak_stop")?,
+            utc_offset: jconf.default::<i32>("utc_offset", 0)?,
+        }),
+        None => None,
+    };
+
     let config = BindingCfg {
-        iec_api,
-        slac_api,
+        connectors,
+        budget_imax: jconf.optional::<u32>("budget_imax")?,
+        slac_retry: slac_retry.unwrap_or(2),
         auth_api,
         engy_api,
         ocpp_api,
+        ocpp_version,
         tic,
         limit,
         basic_charging_enabled: jconf.default("basic_charging_enabled", true)?,
+        fallback: FallbackPolicy {
+            slac_timeout: jconf.default("fallback_slac_timeout", true)?,
+            iso_failure: jconf.default("fallback_iso_failure", true)?,
+            v2g_timeout: jconf.default("fallback_v2g_timeout", false)?,
+        },
+        auth_timeout: jconf.default::<u32>("auth_timeout", 30000)?,
+        auth_hold: jconf.default::<u32>("auth_hold", 60000)?,
+        iso_vmax: jconf.default::<u32>("iso_vmax", 400)?,
+        v2g,
+        solar,
+        tariff,
+        thermal: thermal_config(&jconf)?,
+        safety: safety_config(&jconf)?,
+        meter,
+        ocmf: jconf.optional::<&'static str>("ocmf_verb")?,
+        vendor_id: jconf.optional::<&'static str>("vendor_id")?,
+        pnc_trust_store: jconf.optional::<&'static str>("pnc_trust_store")?,
+        pnc_local_list: jconf.optional::<&'static str>("pnc_local_list")?,
+        contract_imax: jconf.default::<u32>("contract_imax", 32)?,
+        contract_pmax: jconf.default::<u32>("contract_pmax", 22)?,
     };
 
     // create backend API
     let api = AfbApi::new(api)
         .set_info(info)
-        .require_api(iec_api)
         .require_api(engy_api)
         .require_api(auth_api);
 
-    if slac_api.is_some() {
-        api.require_api(slac_api.unwrap());
+    for connector in &config.connectors {
+        api.require_api(connector.iec_api);
+        if connector.energy_api != engy_api {
+            api.require_api(connector.energy_api);
+        }
+        if let Some(slac_api) = connector.slac_api {
+            api.require_api(slac_api);
+        }
+        if let Some(lock) = &connector.lock {
+            api.require_api(lock.api);
+        }
+        if let Some(relay) = &connector.relay {
+            api.require_api(relay.api);
+        }
+        if let Some(phase) = &connector.phase {
+            api.require_api(phase.api);
+        }
     }
 
     if ocpp_api.is_some() {
         api.require_api(ocpp_api.unwrap());
     }
+
+    if let Some(solar) = &config.solar {
+        api.require_api(solar.api);
+    }
+
+    if let Some(thermal) = &config.thermal {
+        api.require_api(thermal.api);
+    }
+
     api.set_callback(Box::new(ApiUserData {
-        iec_api,
-        slac_api,
+        connectors: config
+            .connectors
+            .iter()
+            .map(|connector| (connector.iec_api, connector.slac_api, connector.energy_api))
+            .collect(),
+        solar: config.solar.as_ref().map(|solar| (solar.api, solar.verb)),
+        thermal: config
+            .thermal
+            .as_ref()
+            .map(|thermal| (thermal.api, thermal.verb)),
+        meter: config.meter.is_some(),
         engy_api,
         ocpp_api,
     }));
@@ -13,6 +13,14 @@
 use crate::prelude::*;
 use afbv4::prelude::*;
 use charging::prelude::*;
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::stack::Stack;
+use openssl::x509::store::X509StoreBuilder;
+use openssl::x509::{X509StoreContext, X509};
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
 use typesv4::prelude::*;
 
 struct OcppEvtCtx {
@@ -76,6 +84,79 @@ fn engy_iavail_cb(evt: &AfbEventMsg, args: &AfbRqtData, ctx: &AfbCtxData) -> Res
     Ok(())
 }
 
+struct EngyEnergyCtx {
+    mgr: &'static ManagerHandle,
+}
+
+fn engy_energy_cb(evt: &AfbEventMsg, args: &AfbRqtData, ctx: &AfbCtxData) -> Result<(), AfbError> {
+    let ctx = ctx.get_ref::<EngyEnergyCtx>()?;
+    let msg = args.get::<&MeterDataSet>(0)?;
+
+    afb_log_msg!(Debug, evt, "engy_energy:{:?}", msg.total);
+    ctx.mgr.engy_energy(msg)?;
+    Ok(())
+}
+
+struct EngyCurrentCtx {
+    mgr: &'static ManagerHandle,
+}
+
+fn engy_current_cb(evt: &AfbEventMsg, args: &AfbRqtData, ctx: &AfbCtxData) -> Result<(), AfbError> {
+    let ctx = ctx.get_ref::<EngyCurrentCtx>()?;
+    let msg = args.get::<&MeterDataSet>(0)?;
+
+    afb_log_msg!(Debug, evt, "engy_current:{:?}", msg.total);
+    ctx.mgr.engy_current(msg)?;
+    Ok(())
+}
+
+struct EngySetpointCtx {
+    mgr: &'static ManagerHandle,
+}
+
+fn engy_setpoint_cb(
+    evt: &AfbEventMsg,
+    args: &AfbRqtData,
+    ctx: &AfbCtxData,
+) -> Result<(), AfbError> {
+    let ctx = ctx.get_ref::<EngySetpointCtx>()?;
+    let setpoint = args.get::<i32>(0)?;
+
+    afb_log_msg!(Debug, evt, "engy_setpoint:{}", setpoint);
+    ctx.mgr.v2g_setpoint(setpoint)?;
+    Ok(())
+}
+
+struct SolarGridCtx {
+    mgr: &'static ManagerHandle,
+}
+
+fn solar_grid_cb(evt: &AfbEventMsg, args: &AfbRqtData, ctx: &AfbCtxData) -> Result<(), AfbError> {
+    let ctx = ctx.get_ref::<SolarGridCtx>()?;
+    let grid = args.get::<i32>(0)?;
+
+    afb_log_msg!(Debug, evt, "solar_grid:{}", grid);
+    ctx.mgr.solar_grid(grid)?;
+    Ok(())
+}
+
+struct ThermalSampleCtx {
+    mgr: &'static ManagerHandle,
+}
+
+fn thermal_sample_cb(
+    evt: &AfbEventMsg,
+    args: &AfbRqtData,
+    ctx: &AfbCtxData,
+) -> Result<(), AfbError> {
+    let ctx = ctx.get_ref::<ThermalSampleCtx>()?;
+    let sample = args.get::<&ThermalSample>(0)?;
+
+    afb_log_msg!(Debug, evt, "thermal_sample:{:?}", sample);
+    ctx.mgr.thermal_sample(sample)?;
+    Ok(())
+}
+
 struct SlacEvtCtx {
     mgr: &'static ManagerHandle,
 }
@@ -184,6 +265,43 @@ fn iso_state_cb(rqt: &AfbRequest, args: &AfbRqtData, ctx: &AfbCtxData) -> Result
     Ok(())
 }
 
+struct IsoParamCtx {
+    mgr: &'static ManagerHandle,
+}
+
+fn iso_param_cb(rqt: &AfbRequest, _args: &AfbRqtData, ctx: &AfbCtxData) -> Result<(), AfbError> {
+    let ctx = ctx.get_ref::<IsoParamCtx>()?;
+    let param = ctx.mgr.iso_charge_param()?;
+    rqt.reply(param, 0);
+    Ok(())
+}
+
+struct IsoFallbackCtx {
+    mgr: &'static ManagerHandle,
+}
+
+fn iso_fallback_cb(rqt: &AfbRequest, args: &AfbRqtData, ctx: &AfbCtxData) -> Result<(), AfbError> {
+    let ctx = ctx.get_ref::<IsoFallbackCtx>()?;
+    let reason = args.get::<&FallbackReason>(0)?;
+
+    ctx.mgr.iso_fallback(*reason)?;
+    rqt.reply(AFB_NO_DATA, 0);
+    Ok(())
+}
+
+struct EvInfoCtx {
+    mgr: &'static ManagerHandle,
+}
+
+fn ev_info_cb(rqt: &AfbRequest, args: &AfbRqtData, ctx: &AfbCtxData) -> Result<(), AfbError> {
+    let ctx = ctx.get_ref::<EvInfoCtx>()?;
+    let info = args.get::<&EvInfo>(0)?;
+
+    ctx.mgr.set_ev_info(info)?;
+    rqt.reply(AFB_NO_DATA, 0);
+    Ok(())
+}
+
 struct PaymentOptionCtx {
     mgr: &'static ManagerHandle,
 }
@@ -255,6 +373,283 @@ fn remote_power_callback(
     Ok(())
 }
 
+struct V2gSetpointCtx {
+    mgr: &'static ManagerHandle,
+}
+
+fn v2g_setpoint_callback(
+    request: &AfbRequest,
+    args: &AfbRqtData,
+    ctx: &AfbCtxData,
+) -> Result<(), AfbError> {
+    let ctx = ctx.get_ref::<V2gSetpointCtx>()?;
+    let setpoint = args.get::<i32>(0)?;
+
+    let applied = ctx.mgr.v2g_setpoint(setpoint)?;
+    request.reply(applied, 0);
+    Ok(())
+}
+
+struct PncContractCtx {
+    mgr: &'static ManagerHandle,
+}
+
+fn pnc_contract_callback(
+    request: &AfbRequest,
+    args: &AfbRqtData,
+    ctx: &AfbCtxData,
+) -> Result<(), AfbError> {
+    let ctx = ctx.get_ref::<PncContractCtx>()?;
+    let contract = args.get::<&PncContract>(0)?;
+
+    let status = ctx.mgr.pnc_authorize(contract)?;
+    request.reply(status, 0);
+    Ok(())
+}
+
+struct SolarModeCtx {
+    mgr: &'static ManagerHandle,
+}
+
+fn solar_mode_callback(
+    request: &AfbRequest,
+    args: &AfbRqtData,
+    ctx: &AfbCtxData,
+) -> Result<(), AfbError> {
+    let ctx = ctx.get_ref::<SolarModeCtx>()?;
+    let mode = args.get::<&SolarMode>(0)?;
+
+    ctx.mgr.solar_mode(*mode)?;
+    request.reply(AFB_NO_DATA, 0);
+    Ok(())
+}
+
+struct ChargingModeCtx {
+    mgr: &'static ManagerHandle,
+}
+
+fn charging_mode_callback(
+    request: &AfbRequest,
+    args: &AfbRqtData,
+    ctx: &AfbCtxData,
+) -> Result<(), AfbError> {
+    let ctx = ctx.get_ref::<ChargingModeCtx>()?;
+    let mode = args.get::<&ChargingMode>(0)?;
+
+    ctx.mgr.charging_mode(*mode)?;
+    request.reply(AFB_NO_DATA, 0);
+    Ok(())
+}
+
+struct AuthorizeCtx {
+    mgr: &'static ManagerHandle,
+}
+
+fn authorize_callback(
+    request: &AfbRequest,
+    _args: &AfbRqtData,
+    ctx: &AfbCtxData,
+) -> Result<(), AfbError> {
+    let ctx = ctx.get_ref::<AuthorizeCtx>()?;
+
+    let status = ctx.mgr.authorize()?;
+    request.reply(status, 0);
+    Ok(())
+}
+
+struct AvailabilityCtx {
+    mgr: &'static ManagerHandle,
+}
+
+fn availability_callback(
+    request: &AfbRequest,
+    args: &AfbRqtData,
+    ctx: &AfbCtxData,
+) -> Result<(), AfbError> {
+    let ctx = ctx.get_ref::<AvailabilityCtx>()?;
+
+    let availability = args.get::<&OcppAvailability>(0)?;
+    let status = ctx.mgr.availability(*availability)?;
+
+    request.reply(status, 0);
+    Ok(())
+}
+
+// vendor DataTransfer messages accepted from CSMS
+struct VendorDataTransfer {}
+
+impl DataTransferHandler for VendorDataTransfer {
+    fn transfer(
+        &self,
+        mgr: &'static ManagerHandle,
+        request: &OcppDataTransfer,
+    ) -> Result<OcppDataTransferRsp, AfbError> {
+        let response = match request.message_id.as_deref() {
+            Some("get-state") => {
+                let state = mgr.get_state()?;
+                match serde_json::to_string(&*state) {
+                    Ok(data) => OcppDataTransferRsp {
+                        status: OcppDataTransferStatus::Accepted,
+                        data: Some(data),
+                    },
+                    Err(error) => return afb_error!("chmgr-data-transfer", "{}", error),
+                }
+            }
+            // data is a json charging mode, e.g. "solar"
+            Some("set-mode") => {
+                let mode = match request
+                    .data
+                    .as_deref()
+                    .map(serde_json::from_str::<ChargingMode>)
+                {
+                    Some(Ok(value)) => value,
+                    _ => return afb_error!("chmgr-data-transfer", "set-mode invalid data"),
+                };
+                mgr.charging_mode(mode)?;
+                OcppDataTransferRsp {
+                    status: OcppDataTransferStatus::Accepted,
+                    data: None,
+                }
+            }
+            _ => OcppDataTransferRsp {
+                status: OcppDataTransferStatus::UnknownMessageId,
+                data: None,
+            },
+        };
+        Ok(response)
+    }
+}
+
+// certificates are PEM, or base64 DER as carried by iso15118 messages
+fn pnc_certificate(data: &str) -> Result<X509, AfbError> {
+    let cert = match X509::from_pem(data.as_bytes()) {
+        Ok(value) => Ok(value),
+        Err(_) => openssl::base64::decode_block(data.trim()).and_then(|der| X509::from_der(&der)),
+    };
+    match cert {
+        Ok(value) => Ok(value),
+        Err(error) => afb_error!("pnc-trust-store", "invalid certificate:{}", error),
+    }
+}
+
+// trusted V2G/MO roots are PEM files named after their sha256 fingerprint
+struct PncTrustStore {
+    path: &'static str,
+    local_list: Option<&'static str>,
+}
+
+impl PncTrustStore {
+    fn new(path: &'static str, local_list: Option<&'static str>) -> Self {
+        PncTrustStore { path, local_list }
+    }
+}
+
+impl ContractValidator for PncTrustStore {
+    fn validate(&self, contract: &PncContract) -> Result<(), AfbError> {
+        let fingerprint = contract.root_fingerprint.to_lowercase();
+        if fingerprint.is_empty() || !fingerprint.chars().all(|c| c.is_ascii_hexdigit()) {
+            return afb_error!("pnc-trust-store", "invalid root fingerprint");
+        }
+        let root = match fs::read(Path::new(self.path).join(&fingerprint)) {
+            Ok(data) => data,
+            Err(_) => return afb_error!("pnc-trust-store", "untrusted root:{}", fingerprint),
+        };
+        let root = match X509::from_pem(&root) {
+            Ok(value) => value,
+            Err(error) => return afb_error!("pnc-trust-store", "root:{} {}", fingerprint, error),
+        };
+        let digest = match root.digest(MessageDigest::sha256()) {
+            Ok(value) => value
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<String>(),
+            Err(error) => return afb_error!("pnc-trust-store", "root:{} {}", fingerprint, error),
+        };
+        if digest != fingerprint {
+            return afb_error!(
+                "pnc-trust-store",
+                "root:{} fingerprint mismatch",
+                fingerprint
+            );
+        }
+
+        // contract certificate common name is the emaid
+        let leaf = pnc_certificate(&contract.certificate)?;
+        let subject = leaf
+            .subject_name()
+            .entries_by_nid(Nid::COMMONNAME)
+            .next()
+            .and_then(|entry| entry.data().as_utf8().ok())
+            .map(|name| name.replace('-', "").to_uppercase());
+        if subject != Some(contract.emaid.replace('-', "").to_uppercase()) {
+            return afb_error!(
+                "pnc-trust-store",
+                "emaid does not match contract certificate"
+            );
+        }
+
+        // leaf and sub-CAs must chain up to the stored root, signatures and validity checked
+        let mut chain = match Stack::<X509>::new() {
+            Ok(value) => value,
+            Err(error) => return afb_error!("pnc-trust-store", "{}", error),
+        };
+        for data in &contract.chain {
+            if let Err(error) = chain.push(pnc_certificate(data)?) {
+                return afb_error!("pnc-trust-store", "{}", error);
+            }
+        }
+        let verified = X509StoreBuilder::new()
+            .and_then(|mut builder| {
+                builder.add_cert(root)?;
+                Ok(builder.build())
+            })
+            .and_then(|store| {
+                let mut context = X509StoreContext::new()?;
+                context.init(&store, &leaf, &chain, |context| {
+                    if context.verify_cert()? {
+                        Ok(None)
+                    } else {
+                        Ok(Some(context.error().to_string()))
+                    }
+                })
+            });
+        match verified {
+            Ok(None) => {}
+            Ok(Some(info)) => {
+                return afb_error!("pnc-trust-store", "contract chain rejected:{}", info)
+            }
+            Err(error) => {
+                return afb_error!("pnc-trust-store", "chain verification fail:{}", error)
+            }
+        }
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        if contract.not_after <= now {
+            return afb_error!("pnc-trust-store", "contract certificate expired");
+        }
+        Ok(())
+    }
+
+    // local list is read on each check, it may be provisioned or updated at any time
+    fn is_listed(&self, emaid: &str) -> bool {
+        let filename = match self.local_list {
+            Some(value) => value,
+            None => return false,
+        };
+        match fs::read_to_string(filename) {
+            Ok(data) => data.lines().any(|line| line.trim() == emaid),
+            // list not provisioned yet, contracts are refused until then
+            Err(error) => {
+                afb_log_msg!(Warning, None, "pnc local list:{} {}", filename, error);
+                false
+            }
+        }
+    }
+}
+
 struct TimerCtx {
     mgr: &'static ManagerHandle,
     evt: &'static AfbEvent,
@@ -268,25 +663,103 @@ fn timer_callback(_timer: &AfbTimer, _decount: u32, ctx: &AfbCtxData) -> Result<
     Ok(())
 }
 
-pub(crate) fn register_verbs(
+struct TariffTimerCtx {
+    mgr: &'static ManagerHandle,
+}
+
+// re-evaluate off-peak window for scheduled mode
+fn tariff_timer_cb(_timer: &AfbTimer, _decount: u32, ctx: &AfbCtxData) -> Result<(), AfbError> {
+    let ctx = ctx.get_ref::<TariffTimerCtx>()?;
+    ctx.mgr.tariff_tic()
+}
+
+struct MeterTimerCtx {
+    mgr: &'static ManagerHandle,
+}
+
+// stale meter readings detection
+fn meter_timer_cb(_timer: &AfbTimer, _decount: u32, ctx: &AfbCtxData) -> Result<(), AfbError> {
+    let ctx = ctx.get_ref::<MeterTimerCtx>()?;
+    ctx.mgr.meter_tic()
+}
+
+struct SafetyTimerCtx {
+    mgr: &'static ManagerHandle,
+}
+
+// periodic self-test while connector is free
+fn safety_timer_cb(_timer: &AfbTimer, _decount: u32, ctx: &AfbCtxData) -> Result<(), AfbError> {
+    let ctx = ctx.get_ref::<SafetyTimerCtx>()?;
+    ctx.mgr.safety_tic()
+}
+
+// single connector keeps legacy names, otherwise verbs/events are prefixed with connector id
+fn connector_name(prefix: &str, name: &str) -> &'static str {
+    to_static_str(format!("{}{}", prefix, name))
+}
+
+fn register_connector(
     apiv4: AfbApiV4,
     api: &mut AfbApi,
-    config: BindingCfg,
+    config: &BindingCfg,
+    connector: &ConnectorCfg,
+    budget: Option<&'static PowerBudget>,
+    prefix: &str,
 ) -> Result<(), AfbError> {
-    let msg_evt = AfbEvent::new("msg");
+    let msg_evt = AfbEvent::new(connector_name(prefix, "msg"));
     let manager = ManagerHandle::new(
         apiv4,
-        config.auth_api,
-        config.iec_api,
-        config.engy_api,
-        config.ocpp_api,
+        ManagerConfig {
+            auth_api: config.auth_api,
+            iec_api: connector.iec_api,
+            engy_api: connector.energy_api,
+            ocpp_api: config.ocpp_api,
+            slac_api: connector.slac_api,
+            slac_retry: match connector.slac_api {
+                Some(_) => config.slac_retry,
+                None => 0,
+            },
+            ocpp_version: config.ocpp_version,
+            evse_id: connector.evse_id,
+            connector_id: connector.connector_id,
+            basic_charging_enabled: config.basic_charging_enabled,
+            fallback: config.fallback.clone(),
+            auth_timeout: config.auth_timeout,
+            auth_hold: config.auth_hold,
+            contract_imax: config.contract_imax,
+            contract_pmax: config.contract_pmax,
+            lock: connector.lock.clone(),
+            relay: connector.relay.clone(),
+            phase: connector.phase.clone(),
+            iso_vmax: config.iso_vmax,
+            v2g: config.v2g.clone(),
+            solar: config.solar.clone(),
+            tariff: config.tariff.clone(),
+            thermal: config.thermal.clone(),
+            safety: config.safety.clone(),
+            meter: config.meter.clone(),
+            ocmf: config.ocmf,
+            budget,
+        },
         msg_evt,
-        config.basic_charging_enabled,
     );
 
-    let state_event = AfbEvent::new("state");
+    if let Some(vendor_id) = config.vendor_id {
+        manager.set_data_transfer(vendor_id, Box::new(VendorDataTransfer {}));
+    }
+
+    if let Some(path) = config.pnc_trust_store {
+        let trust_store = PncTrustStore::new(path, config.pnc_local_list);
+        manager.set_contract_validator(Box::new(trust_store));
+    }
+
+    if let Some(budget) = budget {
+        budget.add_connector(manager);
+    }
+
+    let state_event = AfbEvent::new(connector_name(prefix, "state"));
     if config.tic > 0 {
-        AfbTimer::new("tic-timer")
+        AfbTimer::new(connector_name(prefix, "tic-timer"))
             .set_period(config.tic)
             .set_decount(0)
             .set_callback(timer_callback)
@@ -297,8 +770,44 @@ pub(crate) fn register_verbs(
             .start()?;
     }
 
-    let state_verb = AfbVerb::new("charging-state")
-        .set_name("state")
+    if config.tariff.is_some() {
+        AfbTimer::new(connector_name(prefix, "tariff-timer"))
+            .set_period(60000)
+            .set_decount(0)
+            .set_callback(tariff_timer_cb)
+            .set_context(TariffTimerCtx { mgr: manager })
+            .start()?;
+    }
+
+    if let Some(safety) = &config.safety {
+        if safety.period > 0 {
+            AfbTimer::new(connector_name(prefix, "safety-timer"))
+                .set_period(safety.period)
+                .set_decount(0)
+                .set_callback(safety_timer_cb)
+                .set_context(SafetyTimerCtx { mgr: manager })
+                .start()?;
+        }
+    }
+
+    if let Some(meter) = &config.meter {
+        AfbTimer::new(connector_name(prefix, "meter-timer"))
+            .set_period(meter.stale)
+            .set_decount(0)
+            .set_callback(meter_timer_cb)
+            .set_context(MeterTimerCtx { mgr: manager })
+            .start()?;
+
+        let current_handler = AfbEvtHandler::new(connector_name(prefix, "current-evt"))
+            .set_pattern(to_static_str(format!("{}/current", connector.energy_api)))
+            .set_callback(engy_current_cb)
+            .set_context(EngyCurrentCtx { mgr: manager })
+            .finalize()?;
+        api.add_evt_handler(current_handler);
+    }
+
+    let state_verb = AfbVerb::new(connector_name(prefix, "charging-state"))
+        .set_name(connector_name(prefix, "state"))
         .set_info("current charging state")
         .set_actions("['read','subscribe','unsubscribe']")?
         .set_callback(state_request_cb)
@@ -308,79 +817,180 @@ pub(crate) fn register_verbs(
         })
         .finalize()?;
 
-    let reserve_verb = AfbVerb::new("reserve-charger")
-        .set_name("reserve")
+    let reserve_verb = AfbVerb::new(connector_name(prefix, "reserve-charger"))
+        .set_name(connector_name(prefix, "reserve"))
         .set_info("reserve charging station start/stop data")
         .set_actions("['now','delay','cancel']")?
         .set_callback(reserve_charger_cb)
         .set_context(ReserveChargerCtx { mgr: manager })
         .finalize()?;
 
-    let subscribe_verb = AfbVerb::new("subscribe-msg")
-        .set_name("subscribe")
+    let subscribe_verb = AfbVerb::new(connector_name(prefix, "subscribe-msg"))
+        .set_name(connector_name(prefix, "subscribe"))
         .set_callback(subscribe_callback)
         .set_context(SubscribeCtx { event: msg_evt })
         .set_info("subscribe charging events")
         .set_usage("true|false")
         .finalize()?;
 
-    let iso_state_verb = AfbVerb::new("iso-state")
+    let iso_state_verb = AfbVerb::new(connector_name(prefix, "iso-state"))
         .set_info("selected iso state")
         .set_callback(iso_state_cb)
         .set_context(IsoStateCtx { mgr: manager })
         .finalize()?;
 
-    let payment_option_verb = AfbVerb::new("payment-option")
+    let iso_param_verb = AfbVerb::new(connector_name(prefix, "iso-param"))
+        .set_info("iso15118 charge parameters and schedule")
+        .set_callback(iso_param_cb)
+        .set_context(IsoParamCtx { mgr: manager })
+        .finalize()?;
+
+    let iso_fallback_verb = AfbVerb::new(connector_name(prefix, "iso-fallback"))
+        .set_info("iso15118 session failure, fall back to basic charging")
+        .set_callback(iso_fallback_cb)
+        .set_context(IsoFallbackCtx { mgr: manager })
+        .set_usage("'isofailure'|'v2gtimeout'")
+        .finalize()?;
+
+    let ev_info_verb = AfbVerb::new(connector_name(prefix, "ev-info"))
+        .set_info("vehicle data reported by iso15118 stack")
+        .set_callback(ev_info_cb)
+        .set_context(EvInfoCtx { mgr: manager })
+        .finalize()?;
+
+    let payment_option_verb = AfbVerb::new(connector_name(prefix, "payment-option"))
         .set_info("selected payment option")
         .set_callback(payment_option_cb)
         .set_context(PaymentOptionCtx { mgr: manager })
         .finalize()?;
 
-    let set_slac_state_verb = AfbVerb::new("set_slac_status")
+    let set_slac_state_verb = AfbVerb::new(connector_name(prefix, "set_slac_status"))
         .set_info("Set SLAC Status")
         .set_callback(on_set_slac_status)
         .set_context(SlacEvtCtx { mgr: manager })
         .finalize()?;
 
-    let iec_handler = AfbEvtHandler::new("iec-evt")
-        .set_pattern(to_static_str(format!("{}/*", config.iec_api)))
+    let iec_handler = AfbEvtHandler::new(connector_name(prefix, "iec-evt"))
+        .set_pattern(to_static_str(format!("{}/*", connector.iec_api)))
         .set_callback(iec_event_cb)
         .set_context(IecEvtCtx { mgr: manager })
         .finalize()?;
 
-    let iover_handler = AfbEvtHandler::new("iover-evt")
-        .set_pattern(to_static_str(format!("{}/iover", config.engy_api)))
+    let iover_handler = AfbEvtHandler::new(connector_name(prefix, "iover-evt"))
+        .set_pattern(to_static_str(format!("{}/iover", connector.energy_api)))
         .set_callback(engy_iover_cb)
         .set_context(EngyIoverCtx { mgr: manager })
         .finalize()?;
 
-    let ignore_handler = AfbEvtHandler::new("over-limit")
-        .set_pattern(to_static_str(format!("{}/over-limit", config.engy_api)))
+    let ignore_handler = AfbEvtHandler::new(connector_name(prefix, "over-limit"))
+        .set_pattern(to_static_str(format!(
+            "{}/over-limit",
+            connector.energy_api
+        )))
         .set_callback(engy_ignore_cb)
         .set_context(IgnoreCtx {})
         .finalize()?;
 
-    let iavail_handler = AfbEvtHandler::new("iavail-evt")
+    let iavail_handler = AfbEvtHandler::new(connector_name(prefix, "iavail-evt"))
         .set_pattern(to_static_str(format!("{}/iavail", config.engy_api)))
         .set_callback(engy_iavail_cb)
         .set_context(EngyIavailCtx { mgr: manager })
         .finalize()?;
 
-    let remote_power_verb = AfbVerb::new("remote_power")
+    let energy_handler = AfbEvtHandler::new(connector_name(prefix, "energy-evt"))
+        .set_pattern(to_static_str(format!("{}/energy", connector.energy_api)))
+        .set_callback(engy_energy_cb)
+        .set_context(EngyEnergyCtx { mgr: manager })
+        .finalize()?;
+
+    let setpoint_handler = AfbEvtHandler::new(connector_name(prefix, "setpoint-evt"))
+        .set_pattern(to_static_str(format!("{}/setpoint", connector.energy_api)))
+        .set_callback(engy_setpoint_cb)
+        .set_context(EngySetpointCtx { mgr: manager })
+        .finalize()?;
+
+    let v2g_setpoint_verb = AfbVerb::new(connector_name(prefix, "v2g-setpoint"))
+        .set_callback(v2g_setpoint_callback)
+        .set_context(V2gSetpointCtx { mgr: manager })
+        .set_info("charge/discharge power setpoint (W), negative to discharge")
+        .set_usage("watts")
+        .finalize()?;
+
+    let charging_mode_verb = AfbVerb::new(connector_name(prefix, "charging-mode"))
+        .set_name(connector_name(prefix, "mode"))
+        .set_callback(charging_mode_callback)
+        .set_context(ChargingModeCtx { mgr: manager })
+        .set_info("select charging mode before or during session")
+        .set_usage("'fast'|'eco'|'solar'|'scheduled'")
+        .finalize()?;
+
+    let solar_mode_verb = AfbVerb::new(connector_name(prefix, "solar-mode"))
+        .set_callback(solar_mode_callback)
+        .set_context(SolarModeCtx { mgr: manager })
+        .set_info("charge from PV surplus for current session")
+        .set_usage("'off'|'solar'|'hybrid'")
+        .finalize()?;
+
+    let remote_power_verb = AfbVerb::new(connector_name(prefix, "remote_power"))
         .set_callback(remote_power_callback)
         .set_context(RemotePowerData { mgr: manager })
         .set_info("allow power (true/false)")
         .set_usage("true/false")
         .finalize()?;
 
+    let authorize_verb = AfbVerb::new(connector_name(prefix, "authorize"))
+        .set_callback(authorize_callback)
+        .set_context(AuthorizeCtx { mgr: manager })
+        .set_info("request idp-login, before or after plug-in")
+        .finalize()?;
+
+    let pnc_contract_verb = AfbVerb::new(connector_name(prefix, "pnc-contract"))
+        .set_callback(pnc_contract_callback)
+        .set_context(PncContractCtx { mgr: manager })
+        .set_info("Plug&Charge contract certificate from iso stack")
+        .finalize()?;
+
+    let availability_verb = AfbVerb::new(connector_name(prefix, "availability"))
+        .set_callback(availability_callback)
+        .set_context(AvailabilityCtx { mgr: manager })
+        .set_info("change charger availability")
+        .set_usage("'operative'|'inoperative'")
+        .finalize()?;
+
     api.add_evt_handler(iover_handler);
     api.add_evt_handler(iavail_handler);
     api.add_evt_handler(iec_handler);
     api.add_evt_handler(ignore_handler);
+    api.add_evt_handler(energy_handler);
+    api.add_evt_handler(setpoint_handler);
+
+    if let Some(solar) = &config.solar {
+        let solar_handler = AfbEvtHandler::new(connector_name(prefix, "solar-evt"))
+            .set_pattern(to_static_str(format!("{}/{}", solar.api, solar.verb)))
+            .set_callback(solar_grid_cb)
+            .set_context(SolarGridCtx { mgr: manager })
+            .finalize()?;
+        api.add_evt_handler(solar_handler);
+    }
+
+    if let Some(thermal) = &config.thermal {
+        let thermal_handler = AfbEvtHandler::new(connector_name(prefix, "thermal-evt"))
+            .set_pattern(to_static_str(format!("{}/{}", thermal.api, thermal.verb)))
+            .set_callback(thermal_sample_cb)
+            .set_context(ThermalSampleCtx { mgr: manager })
+            .finalize()?;
+        api.add_evt_handler(thermal_handler);
+    }
 
-    if config.ocpp_api.is_some() {
-        let ocpp_handler = AfbEvtHandler::new("ocpp-evt")
-            .set_pattern(to_static_str(format!("{}/*", config.ocpp_api.unwrap())))
+    if let Some(ocpp_api) = config.ocpp_api {
+        // with several connectors, ocpp binding publishes per connector id
+        let pattern = if config.connectors.len() > 1 {
+            format!("{}/{}/*", ocpp_api, connector.connector_id)
+        } else {
+            format!("{}/*", ocpp_api)
+        };
+        let ocpp_handler = AfbEvtHandler::new(connector_name(prefix, "ocpp-evt"))
+            .set_pattern(to_static_str(pattern))
             .set_callback(ocpp_event_cb)
             .set_context(OcppEvtCtx { mgr: manager })
             .finalize()?;
@@ -388,16 +998,16 @@ pub(crate) fn register_verbs(
         api.add_evt_handler(ocpp_handler);
     }
 
-    if config.slac_api.is_some() {
-        let slac_handler = AfbEvtHandler::new("slac-evt")
-            .set_pattern(to_static_str(format!("{}/*", config.slac_api.unwrap())))
+    if let Some(slac_api) = connector.slac_api {
+        let slac_handler = AfbEvtHandler::new(connector_name(prefix, "slac-evt"))
+            .set_pattern(to_static_str(format!("{}/*", slac_api)))
             .set_callback(slac_event_cb)
             .set_context(SlacEvtCtx { mgr: manager })
             .finalize()?;
         api.add_evt_handler(slac_handler);
     }
 
-    let set_service_status_verb = AfbVerb::new("set-service-status")
+    let set_service_status_verb = AfbVerb::new(connector_name(prefix, "set-service-status"))
         .set_info("Notify of a service status")
         .set_callback(set_service_status_cb)
         .set_context(ServiceStatusCtx { mgr: manager })
@@ -408,10 +1018,41 @@ pub(crate) fn register_verbs(
     api.add_verb(reserve_verb);
     api.add_verb(subscribe_verb);
     api.add_verb(iso_state_verb);
+    api.add_verb(iso_param_verb);
+    api.add_verb(ev_info_verb);
+    api.add_verb(iso_fallback_verb);
     api.add_verb(payment_option_verb);
     api.add_verb(remote_power_verb);
+    api.add_verb(v2g_setpoint_verb);
+    api.add_verb(solar_mode_verb);
+    api.add_verb(charging_mode_verb);
+    api.add_verb(authorize_verb);
+    api.add_verb(pnc_contract_verb);
+    api.add_verb(availability_verb);
     api.add_verb(set_slac_state_verb);
     api.add_verb(set_service_status_verb);
 
     Ok(())
 }
+
+pub(crate) fn register_verbs(
+    apiv4: AfbApiV4,
+    api: &mut AfbApi,
+    config: BindingCfg,
+) -> Result<(), AfbError> {
+    // several connectors always share site current, budget_imax is optional
+    let budget = if config.budget_imax.is_some() || config.connectors.len() > 1 {
+        Some(PowerBudget::new(config.budget_imax))
+    } else {
+        None
+    };
+    for connector in &config.connectors {
+        let prefix = if config.connectors.len() > 1 {
+            format!("{}/", connector.connector_id)
+        } else {
+            String::new()
+        };
+        register_connector(apiv4, api, &config, connector, budget, &prefix)?;
+    }
+    Ok(())
+}
@@ -23,9 +23,42 @@ pub enum Iec6185Msg {
     Error(String),
 }
 
+// pre-charge self-tests run by iec firmware
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum SafetyTest {
+    Rcd,
+    RelayWeld,
+    Pe,
+}
+
+AfbDataConverter!(safety_request, SafetyRequest);
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "lowercase")]
+pub struct SafetyRequest {
+    pub tests: Vec<SafetyTest>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "lowercase")]
+pub struct SafetyResult {
+    pub test: SafetyTest,
+    pub passed: bool,
+    #[serde(default)]
+    pub info: Option<String>,
+}
+
+AfbDataConverter!(safety_report, SafetyReport);
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "lowercase")]
+pub struct SafetyReport {
+    pub results: Vec<SafetyResult>,
+}
 
 pub fn am62x_registers() -> Result <(), AfbError> {
     // add binding custom converter
     iec6185_msg::register()?;
+    safety_request::register()?;
+    safety_report::register()?;
     Ok(())
 }
\ No newline at end of file
@@ -10,6 +10,7 @@
  *
  */
 
+use crate::prelude::*;
 use afbv4::prelude::*;
 use serde::{Deserialize, Serialize};
 
@@ -32,6 +33,9 @@ pub struct AuthState {
     pub imax:u32,
     pub pmax: u32,
     pub ocpp_check: bool,
+    // fleet contract refusing basic charging fallback
+    #[serde(default)]
+    pub iso_only: bool,
 }
 
 impl AuthState {
@@ -42,10 +46,26 @@ impl AuthState {
             pmax: 0,
             tagid: String::new(),
             ocpp_check: true,
+            iso_only: false,
         }
     }
 }
 
+// contract certificate chain received from iso stack, certificates are PEM encoded
+AfbDataConverter!(pnc_contract, PncContract);
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "lowercase")]
+pub struct PncContract {
+    pub emaid: String,
+    pub certificate: String,
+    pub chain: Vec<String>,
+    // sha256 fingerprint (hex) of the root the chain anchors to
+    pub root_fingerprint: String,
+    // leaf certificate expiration (seconds since epoch)
+    pub not_after: u64,
+    pub hash_data: Vec<OcppCertHashData>,
+}
+
 AfbDataConverter!(auth_actions, AuthAction);
 #[derive(Serialize, Deserialize, Debug, Default)]
 #[serde(rename_all = "lowercase", tag = "action")]
@@ -60,6 +80,7 @@ pub enum AuthAction {
 pub fn auth_registers() -> Result<(),AfbError> {
     auth_msg::register()?;
     auth_state::register()?;
+    pnc_contract::register()?;
     auth_actions::register()?;
     Ok(())
 }
\ No newline at end of file
@@ -13,7 +13,7 @@
 use crate::prelude::*;
 use afbv4::prelude::*;
 use serde::{Deserialize, Serialize};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 AfbDataConverter!(error_state, ErrorState);
 #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
@@ -69,6 +69,45 @@ pub enum IsoState {
     Unset,
 }
 
+// one PMaxSchedule entry (iso-2 SAScheduleTuple, iso-20 schedule), start in seconds from now
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub struct IsoScheduleEntry {
+    pub start: u32,
+    pub imax: u32,
+    pub pmax: u32,
+}
+
+// charge parameters chmgr provides to the iso15118 stack (A, W, V)
+AfbDataConverter!(iso_charge_param, IsoChargeParam);
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub struct IsoChargeParam {
+    pub imax: u32,
+    pub pmax: u32,
+    pub vmax: u32,
+    pub schedule: Vec<IsoScheduleEntry>,
+    // v2g target power, negative when discharging
+    pub setpoint: i32,
+    pub discharge_imax: u32,
+    pub discharge_pmax: u32,
+}
+
+// vehicle data reported by the iso15118 stack, fields are only known once the EV sent them
+AfbDataConverter!(ev_info, EvInfo);
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "lowercase")]
+pub struct EvInfo {
+    pub evccid: Option<String>,
+    pub mac: Option<String>,
+    // requested energy (Wh)
+    pub energy_request: Option<u32>,
+    // departure time (seconds from now when reported)
+    pub departure: Option<u32>,
+    pub soc: Option<u32>,
+    pub imax: Option<u32>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
 #[serde(rename_all = "lowercase")]
 pub enum PaymentOption {
@@ -86,6 +125,47 @@ pub enum ChargingProtocol {
     Grid2Vehicle,
 }
 
+// reason to abandon iso15118 and fall back to basic charging
+AfbDataConverter!(fallback_reason, FallbackReason);
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum FallbackReason {
+    SlacTimeout,
+    IsoFailure,
+    V2gTimeout,
+}
+
+// user choice for a session, Eco charges a minimum current plus PV surplus
+AfbDataConverter!(charging_mode, ChargingMode);
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum ChargingMode {
+    Fast,
+    Eco,
+    Solar,
+    Scheduled,
+}
+
+// Hybrid: charge at least hybrid_imin and add PV surplus on top
+AfbDataConverter!(solar_mode, SolarMode);
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum SolarMode {
+    Off,
+    Solar,
+    Hybrid,
+}
+
+// Scheduled: inoperative as soon as current session ends
+AfbDataConverter!(availability_state, AvailabilityState);
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum AvailabilityState {
+    Operative,
+    Inoperative,
+    Scheduled,
+}
+
 AfbDataConverter!(service_status, ServiceStatus);
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "lowercase")]
@@ -103,11 +183,24 @@ pub enum ChargingMsg {
     Plugged(PlugState),
     Power(PowerRequest),
     Iso(IsoState),
+    Slac(SlacStatus),
     Auth(AuthMsg),
     State(ChargingState),
     Reservation(ReservationStatus),
     Protocol(ChargingProtocol),
     Payment(PaymentOption),
+    Session(SessionRecord),
+    Availability(AvailabilityState),
+    IsoParam(IsoChargeParam),
+    Setpoint(i32),
+    Phases(u32),
+    Solar(SolarMode),
+    Mode(ChargingMode),
+    Thermal(ThermalState),
+    Safety(SafetyDiag),
+    Relay(RelayDiag),
+    Meter(MeterDiag),
+    EvInfo(EvInfo),
     ServiceStatus { name: String, status: ServiceStatus },
 }
 
@@ -116,8 +209,150 @@ AfbDataConverter!(reservation_state, ReservationState);
 #[serde(rename_all = "lowercase")]
 pub struct ReservationState {
     pub id: i32,
+    pub tagid: String,
+    pub start: Duration,
+    pub stop: Duration,
+}
+
+// SLAC attempts of a session, durations in ms
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "lowercase")]
+pub struct SlacDiag {
+    #[serde(skip)]
+    pub start: Option<Instant>,
+    pub attempts: u32,
+    pub unmatched: u32,
+    pub timeout: u32,
+    pub last_duration: u32,
+    pub total_duration: u32,
+}
+
+// last self-test run, time since epoch, relay stays open while running or failed
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "lowercase")]
+pub struct SafetyDiag {
+    pub running: bool,
+    pub passed: Option<bool>,
+    pub error: Option<ErrorState>,
+    pub fault: Option<OcppErrorCode>,
+    pub results: Vec<SafetyResult>,
+    pub last: Option<Duration>,
+}
+
+// commanded vs measured contactor state, lockout is only cleared by reboot
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "lowercase")]
+pub struct RelayDiag {
+    pub commanded: Option<bool>,
+    pub measured: Option<bool>,
+    // highest phase current (mA) at last check
+    pub current: Option<i32>,
+    pub lockout: bool,
+    pub error: Option<ErrorState>,
+}
+
+// last meter readings, energy (Wh) and highest phase current (mA), updated since epoch
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "lowercase")]
+pub struct MeterDiag {
+    pub energy: Option<i32>,
+    pub current: Option<i32>,
+    pub updated: Option<Duration>,
+    pub failure: Option<String>,
+    #[serde(skip)]
+    pub settle: Option<Instant>,
+}
+
+AfbDataConverter!(session_record, SessionRecord);
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "lowercase")]
+pub struct SessionRecord {
+    pub tagid: Option<String>,
+    pub ocpp_check: bool,
+    // tagid is a Plug&Charge contract emaid
+    pub pnc: bool,
+    // session started by ocpp RequestStartTransaction
+    pub remote_start: Option<Ocpp201RemoteStart>,
+    pub transaction: bool,
+    // ocpp 2.0.1 transaction id and TransactionEvent sequence
+    pub transaction_id: Option<String>,
+    #[serde(skip)]
+    pub seq_no: u32,
+    pub reservation: Option<i32>,
+    pub ev: Option<EvInfo>,
+    pub slac: SlacDiag,
+    pub safety: Option<SafetyDiag>,
     pub start: Duration,
     pub stop: Duration,
+    pub energy: i32,
+    pub energy_imported: i32,
+    pub energy_exported: i32,
+    #[serde(skip)]
+    pub energy_last: i32,
+    // implausible meter readings during session, not billing grade
+    pub unreliable: bool,
+    // signed meter readings (Eichrecht)
+    pub ocmf: Vec<OcmfSnapshot>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum ThermalSensor {
+    Connector,
+    Relay,
+    Enclosure,
+}
+
+// temperature (°C) event, samples without connector apply to every connector
+AfbDataConverter!(thermal_sample, ThermalSample);
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "lowercase")]
+pub struct ThermalSample {
+    pub sensor: ThermalSensor,
+    #[serde(default)]
+    pub connector: Option<u32>,
+    pub temperature: i32,
+}
+
+// last known temperatures, cutoff stays set until hysteresis is reached
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "lowercase")]
+pub struct ThermalState {
+    pub connector: Option<i32>,
+    pub relay: Option<i32>,
+    pub enclosure: Option<i32>,
+    pub cutoff: bool,
+}
+
+// constraint feeding the effective imax
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LimitSource {
+    Cable,
+    Contract,
+    Ocpp,
+    Site,
+    Budget,
+    Thermal,
+    Mode,
+    Fault,
+}
+
+// current (A) requested by each source, None when the source does not constrain
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub struct ImaxLimits {
+    pub cable: Option<u32>,
+    pub contract: Option<u32>,
+    pub ocpp: Option<u32>,
+    // current available from energy manager
+    pub site: Option<u32>,
+    // share of site current when connectors are charging simultaneously
+    pub budget: Option<u32>,
+    pub thermal: Option<u32>,
+    // solar surplus or tariff hold
+    pub mode: Option<u32>,
+    pub fault: Option<u32>,
 }
 
 AfbDataConverter!(charging_state, ChargingState);
@@ -126,29 +361,104 @@ AfbDataConverter!(charging_state, ChargingState);
 pub struct ChargingState {
     #[serde(skip)]
     pub updated: bool,
+    pub connector_id: u32,
     #[serde(skip)]
     pub reservation: Option<ReservationState>,
+    // running idp-login request, responses from a previous sequence are dropped
+    #[serde(skip)]
+    pub auth_seq: u32,
+    #[serde(skip)]
+    pub auth_start: Option<Instant>,
+    #[serde(skip)]
+    pub auth_contract: Option<AuthState>,
+    // last charge parameters sent to the iso stack, renegotiate when they change
+    #[serde(skip)]
+    pub iso_param: Option<IsoChargeParam>,
+    // ocpp charging profile limit and when it was received (since epoch)
+    pub ocpp_limit: Option<(PowerLimit, Duration)>,
+    // v2g power setpoint (W), negative when discharging
+    pub setpoint: i32,
+    // active phase count, switching waits for the mandatory state B pause
+    pub phases: u32,
+    #[serde(skip)]
+    pub phase_pending: bool,
+    #[serde(skip)]
+    pub phase_switch: Option<Instant>,
+    pub mode: ChargingMode,
+    // scheduled mode outside off-peak window
+    pub tariff_hold: bool,
+    pub solar: SolarMode,
+    // current (A) following PV surplus, 0 while paused
+    pub solar_imax: Option<u32>,
+    pub solar_paused: bool,
+    #[serde(skip)]
+    pub solar_avg: Option<i32>,
+    #[serde(skip)]
+    pub solar_low: Option<Instant>,
+    // effective current, lowest of limits
     pub imax: u32,
+    pub imax_source: Option<LimitSource>,
+    pub limits: ImaxLimits,
+    pub thermal: ThermalState,
+    pub safety: SafetyDiag,
+    pub relay: RelayDiag,
+    pub meter: MeterDiag,
     pub pmax: u32,
     pub plugged: PlugState,
     pub power: PowerRequest,
     pub iso: IsoState,
+    pub slac: SlacStatus,
     pub auth: AuthMsg,
     pub payment: Option<PaymentOption>,
+    pub ev: Option<EvInfo>,
+    // EV departure time (since epoch) computed when iso stack reported it
+    pub departure: Option<Duration>,
+    pub session: SessionRecord,
+    pub availability: AvailabilityState,
+    pub fault: Option<OcppErrorCode>,
 }
 
 impl ChargingState {
     pub fn default() -> Self {
         ChargingState {
             updated: false,
+            connector_id: 1,
             imax: 32, // Fulup TBD should comme from energy mgr
+            imax_source: None,
+            limits: ImaxLimits::default(),
+            thermal: ThermalState::default(),
+            safety: SafetyDiag::default(),
+            relay: RelayDiag::default(),
+            meter: MeterDiag::default(),
             pmax: 22,
             plugged: PlugState::Unknown,
             power: PowerRequest::Idle,
             iso: IsoState::Unset,
+            slac: SlacStatus::IDLE,
             auth: AuthMsg::Idle,
             reservation: None,
             payment: None,
+            ev: None,
+            departure: None,
+            auth_seq: 0,
+            auth_start: None,
+            auth_contract: None,
+            iso_param: None,
+            ocpp_limit: None,
+            setpoint: 0,
+            phases: 3,
+            phase_pending: false,
+            phase_switch: None,
+            mode: ChargingMode::Fast,
+            tariff_hold: false,
+            solar: SolarMode::Off,
+            solar_imax: None,
+            solar_paused: false,
+            solar_avg: None,
+            solar_low: None,
+            session: SessionRecord::default(),
+            availability: AvailabilityState::Operative,
+            fault: None,
         }
     }
 }
@@ -199,8 +509,16 @@ pub fn chmgr_registers() -> Result<(), AfbError> {
     charging_event::register()?;
     reservation_session::register()?;
     reservation_state::register()?;
+    session_record::register()?;
     power_limit::register()?;
     service_status::register()?;
+    availability_state::register()?;
+    iso_charge_param::register()?;
+    fallback_reason::register()?;
+    solar_mode::register()?;
+    charging_mode::register()?;
+    thermal_sample::register()?;
+    ev_info::register()?;
 
     Ok(())
 }
@@ -155,11 +155,40 @@ impl EnergyState {
     }
 }
 
+// OCMF (Eichrecht) signed reading, begin at transaction start, end at stop
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum OcmfContext {
+    Begin,
+    End,
+}
+
+AfbDataConverter!(ocmf_request, OcmfRequest);
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OcmfRequest {
+    pub context: OcmfContext,
+    pub tagid: Option<String>,
+    pub transaction_id: Option<String>,
+}
+
+// payload is the meter signed OCMF string (OCMF|{data}|{signature})
+AfbDataConverter!(ocmf_snapshot, OcmfSnapshot);
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OcmfSnapshot {
+    pub context: OcmfContext,
+    pub timestamp: Duration,
+    pub reading: i32,
+    pub payload: String,
+    pub public_key: Option<String>,
+}
+
 pub fn engy_registers() -> Result<(), AfbError> {
     meter_data_set::register()?;
     config_data_set::register()?;
     energy_actions::register()?;
     energy_state::register()?;
     meter_tag_set::register()?;
+    ocmf_request::register()?;
+    ocmf_snapshot::register()?;
     Ok(())
 }
@@ -19,20 +19,108 @@
  #[serde(rename_all = "lowercase")]
  pub enum OcppMsg {
      Initialized,
-     Reset,
+     Reset(OcppResetType),
      Authorized(bool),
      Transaction(bool,u32),
+     // ocpp 2.0.1 RequestStart/StopTransaction use string transaction ids
+     RemoteTransaction(bool, String),
+     // ocpp 2.0.1 RequestStartTransaction with token type and remoteStartId
+     RemoteStart(Ocpp201RemoteStart),
      Reservation(ReservationSession),
      PowerLimit(PowerLimit),
+     ChangeAvailability(OcppAvailability),
+     UnlockConnector,
+     TriggerMessage(OcppTriggerMessage),
+     DataTransfer(OcppDataTransfer),
      Unknown,
  }
 
+ #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+ #[serde(rename_all = "lowercase")]
+ pub enum OcppTriggerMessage {
+     BootNotification,
+     DiagnosticsStatusNotification,
+     FirmwareStatusNotification,
+     Heartbeat,
+     MeterValues,
+     StatusNotification,
+ }
+
+ AfbDataConverter!(ocpp_data_transfer, OcppDataTransfer);
+ #[derive(Serialize, Deserialize, Debug, Clone)]
+ #[serde(rename_all = "lowercase")]
+ pub struct OcppDataTransfer {
+     pub vendor_id: String,
+     pub message_id: Option<String>,
+     pub data: Option<String>,
+ }
+
+ #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+ #[serde(rename_all = "lowercase")]
+ pub enum OcppDataTransferStatus {
+     Accepted,
+     Rejected,
+     UnknownMessageId,
+     UnknownVendorId,
+ }
+
+ AfbDataConverter!(ocpp_data_transfer_rsp, OcppDataTransferRsp);
+ #[derive(Serialize, Deserialize, Debug, Clone)]
+ #[serde(rename_all = "lowercase")]
+ pub struct OcppDataTransferRsp {
+     pub status: OcppDataTransferStatus,
+     pub data: Option<String>,
+ }
+
+ AfbDataConverter!(ocpp_availability, OcppAvailability);
+ #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+ #[serde(rename_all = "lowercase")]
+ pub enum OcppAvailability {
+     Operative,
+     Inoperative,
+ }
+
+ AfbDataConverter!(ocpp_availability_status, OcppAvailabilityStatus);
+ #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+ #[serde(rename_all = "lowercase")]
+ pub enum OcppAvailabilityStatus {
+     Accepted,
+     Rejected,
+     Scheduled,
+ }
+
+ AfbDataConverter!(ocpp_reset_type, OcppResetType);
+ #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+ #[serde(rename_all = "lowercase")]
+ pub enum OcppResetType {
+     Soft,
+     Hard,
+ }
+
+ #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+ #[serde(rename_all = "lowercase")]
+ pub enum OcppStopReason {
+     DeAuthorized,
+     EmergencyStop,
+     EVDisconnected,
+     HardReset,
+     Local,
+     Other,
+     PowerLoss,
+     Reboot,
+     Remote,
+     SoftReset,
+     UnlockCommand,
+ }
+
  AfbDataConverter!(ocpp_transaction, OcppTransaction);
  #[derive(Serialize, Deserialize, Debug, Clone)]
  #[serde(rename_all = "lowercase")]
  pub enum OcppTransaction {
      Start(String),
      Stop(i32),
+     // transaction closed by the charger itself (reset, unlock, ...)
+     Abort(i32, OcppStopReason),
  }
 
  #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -92,11 +180,207 @@
      }
  }
 
+ // ocpp 1.6 payloads, connector_id lets ocpp binding map chmgr connectors to its own
+ AfbDataConverter!(ocpp16_status_notification, Ocpp16StatusNotification);
+ #[derive(Serialize, Deserialize, Debug, Clone)]
+ #[serde(rename_all = "lowercase")]
+ pub struct Ocpp16StatusNotification {
+     pub connector_id: u32,
+     pub status: OcppChargerStatus,
+ }
+
+ AfbDataConverter!(ocpp16_authorize, Ocpp16Authorize);
+ #[derive(Serialize, Deserialize, Debug, Clone)]
+ #[serde(rename_all = "lowercase")]
+ pub struct Ocpp16Authorize {
+     pub connector_id: u32,
+     pub tagid: String,
+ }
+
+ AfbDataConverter!(ocpp16_transaction, Ocpp16Transaction);
+ #[derive(Serialize, Deserialize, Debug, Clone)]
+ #[serde(rename_all = "lowercase")]
+ pub struct Ocpp16Transaction {
+     pub connector_id: u32,
+     pub action: OcppTransaction,
+ }
+
+ AfbDataConverter!(ocpp16_meter_values, Ocpp16MeterValues);
+ #[derive(Serialize, Deserialize, Debug, Clone)]
+ #[serde(rename_all = "lowercase")]
+ pub struct Ocpp16MeterValues {
+     pub connector_id: u32,
+     pub meter: MeterDataSet,
+ }
+
+ #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+ pub enum OcppVersion {
+     #[serde(rename = "1.6")]
+     V16,
+     #[serde(rename = "2.0.1")]
+     V201,
+ }
+
+ // ocpp 2.0.1 data model, 1.6 messages are translated by chmgr depending on OcppVersion
+ AfbDataConverter!(ocpp201_connector_status, Ocpp201ConnectorStatus);
+ #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+ #[serde(rename_all = "lowercase")]
+ pub enum Ocpp201ConnectorStatus {
+     Available,
+     Occupied,
+     Reserved,
+     Unavailable,
+     Faulted,
+ }
+
+ AfbDataConverter!(ocpp201_status_notification, Ocpp201StatusNotification);
+ #[derive(Serialize, Deserialize, Debug, Clone)]
+ #[serde(rename_all = "lowercase")]
+ pub struct Ocpp201StatusNotification {
+     pub evse_id: u32,
+     pub connector_id: u32,
+     pub status: Ocpp201ConnectorStatus,
+ }
+
+ #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+ #[serde(rename_all = "lowercase")]
+ pub enum Ocpp201IdTokenType {
+     Central,
+     EMAID,
+     ISO14443,
+     ISO15693,
+     KeyCode,
+     Local,
+     MacAddress,
+     NoAuthorization,
+ }
+
+ AfbDataConverter!(ocpp201_id_token, Ocpp201IdToken);
+ #[derive(Serialize, Deserialize, Debug, Clone)]
+ #[serde(rename_all = "lowercase")]
+ pub struct Ocpp201IdToken {
+     pub id_token: String,
+     pub kind: Ocpp201IdTokenType,
+ }
+
+ #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+ #[serde(rename_all = "lowercase")]
+ pub enum Ocpp201TransactionEventType {
+     Started,
+     Updated,
+     Ended,
+ }
+
+ #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+ #[serde(rename_all = "lowercase")]
+ pub enum Ocpp201TriggerReason {
+     AbnormalCondition,
+     Authorized,
+     CablePluggedIn,
+     ChargingStateChanged,
+     Deauthorized,
+     EVDeparted,
+     MeterValuePeriodic,
+     RemoteStart,
+     RemoteStop,
+     ResetCommand,
+     SignedDataReceived,
+     StopAuthorized,
+     Trigger,
+     UnlockCommand,
+ }
+
+ // remoteStartId is echoed in TransactionEvent Started
+ #[derive(Serialize, Deserialize, Debug, Clone)]
+ #[serde(rename_all = "lowercase")]
+ pub struct Ocpp201RemoteStart {
+     pub id_token: Ocpp201IdToken,
+     pub remote_start_id: Option<i32>,
+ }
+
+ AfbDataConverter!(ocpp201_transaction_event, Ocpp201TransactionEvent);
+ #[derive(Serialize, Deserialize, Debug, Clone)]
+ #[serde(rename_all = "lowercase")]
+ pub struct Ocpp201TransactionEvent {
+     pub event_type: Ocpp201TransactionEventType,
+     pub trigger_reason: Ocpp201TriggerReason,
+     pub seq_no: u32,
+     pub transaction_id: String,
+     pub evse_id: u32,
+     pub connector_id: u32,
+     pub id_token: Option<Ocpp201IdToken>,
+     pub stopped_reason: Option<OcppStopReason>,
+     pub meter: Option<i32>,
+     #[serde(default, skip_serializing_if = "Option::is_none")]
+     pub signed_meter: Option<OcppSignedMeter>,
+     #[serde(default, skip_serializing_if = "Option::is_none")]
+     pub remote_start_id: Option<i32>,
+ }
+
+ // iso15118 certificate hash data (OCSPRequestData), hashes are hex encoded
+ #[derive(Serialize, Deserialize, Debug, Clone)]
+ #[serde(rename_all = "lowercase")]
+ pub struct OcppCertHashData {
+     pub hash_algorithm: String,
+     pub issuer_name_hash: String,
+     pub issuer_key_hash: String,
+     pub serial_number: String,
+     pub responder_url: Option<String>,
+ }
+
+ // Plug&Charge Authorize, 1.6 sends it through DataTransfer, 2.0.1 as eMAID id token
+ AfbDataConverter!(ocpp_pnc_authorize, OcppPncAuthorize);
+ #[derive(Serialize, Deserialize, Debug, Clone)]
+ #[serde(rename_all = "lowercase")]
+ pub struct OcppPncAuthorize {
+     pub version: OcppVersion,
+     pub connector_id: u32,
+     pub emaid: String,
+     pub certificate: Option<String>,
+     pub hash_data: Vec<OcppCertHashData>,
+ }
+
+ #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+ #[serde(rename_all = "lowercase")]
+ pub enum OcppReadingContext {
+     TransactionBegin,
+     TransactionEnd,
+ }
+
+ // SignedData sampled value, 1.6 sends Begin as MeterValues and attaches End to StopTransaction
+ AfbDataConverter!(ocpp_signed_meter, OcppSignedMeter);
+ #[derive(Serialize, Deserialize, Debug, Clone)]
+ #[serde(rename_all = "lowercase")]
+ pub struct OcppSignedMeter {
+     pub context: OcppReadingContext,
+     // chmgr connector, 1.6 sends signed readings on their own
+     pub connector_id: u32,
+     pub value: i32,
+     pub signed_data: String,
+     pub encoding: String,
+     pub public_key: Option<String>,
+ }
+
  pub fn ocpp_registers() -> Result<(), AfbError> {
      ocpp_msg::register()?;
      ocpp_state::register()?;
      ocpp_transaction::register()?;
      ocpp_charger_status::register()?;
+     ocpp_reset_type::register()?;
+     ocpp_availability::register()?;
+     ocpp_availability_status::register()?;
+     ocpp_data_transfer::register()?;
+     ocpp_data_transfer_rsp::register()?;
+     ocpp16_status_notification::register()?;
+     ocpp16_authorize::register()?;
+     ocpp16_transaction::register()?;
+     ocpp16_meter_values::register()?;
+     ocpp201_connector_status::register()?;
+     ocpp201_status_notification::register()?;
+     ocpp201_id_token::register()?;
+     ocpp201_transaction_event::register()?;
+     ocpp_pnc_authorize::register()?;
+     ocpp_signed_meter::register()?;
 
      Ok(())
  }
@@ -0,0 +1,516 @@
+/*
+ * Copyright (C) 2015-2022 IoT.bzh Company
+ * Author: Fulup Ar Foll <fulup@iot.bzh>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *   http://www.apache.org/licenses/LICENSE-2.0
+ *
+ */
+
+use crate::prelude::*;
+use afbv4::prelude::*;
+use std::sync::MutexGuard;
+use std::time::Instant;
+use typesv4::prelude::*;
+
+// pending notification period (ms) while waiting for idp-login
+const AUTH_PENDING_TIC: u32 = 1000;
+
+struct AuthLoginCtx {
+    mgr: &'static ManagerHandle,
+    seq: u32,
+}
+
+fn auth_login_cb(_api: &AfbApi, args: &AfbRqtData, ctx: &AfbCtxData) -> Result<(), AfbError> {
+    let ctx = ctx.get_ref::<AuthLoginCtx>()?;
+
+    match args.get::<&AuthState>(0) {
+        Ok(contract) => ctx.mgr.auth_login_rsp(ctx.seq, contract),
+        Err(error) => ctx.mgr.auth_fail(ctx.seq, &error.get_info()),
+    }
+}
+
+struct EngyConfCtx {
+    mgr: &'static ManagerHandle,
+    seq: u32,
+}
+
+fn engy_conf_cb(_api: &AfbApi, args: &AfbRqtData, ctx: &AfbCtxData) -> Result<(), AfbError> {
+    let ctx = ctx.get_ref::<EngyConfCtx>()?;
+
+    match args.get::<&EngyConfSet>(0) {
+        Ok(engy_conf) => ctx.mgr.auth_granted(ctx.seq, engy_conf),
+        Err(error) => ctx.mgr.auth_fail(ctx.seq, &error.get_info()),
+    }
+}
+
+struct AuthTimerCtx {
+    mgr: &'static ManagerHandle,
+    seq: u32,
+}
+
+fn auth_timer_cb(_timer: &AfbTimer, _decount: u32, ctx: &AfbCtxData) -> Result<(), AfbError> {
+    let ctx = ctx.get_ref::<AuthTimerCtx>()?;
+    ctx.mgr.auth_tic(ctx.seq)
+}
+
+fn auth_hold_cb(_timer: &AfbTimer, _decount: u32, ctx: &AfbCtxData) -> Result<(), AfbError> {
+    let ctx = ctx.get_ref::<AuthTimerCtx>()?;
+    ctx.mgr.auth_expire(ctx.seq)
+}
+
+// only the last requested idp-login may update the session
+fn auth_is_pending(data_set: &ChargingState, seq: u32) -> bool {
+    data_set.auth_seq == seq && matches!(data_set.auth, AuthMsg::Pending)
+}
+
+impl ManagerHandle {
+    // authorization request (rfid tap) received independently of plug-in order
+    pub fn authorize(&'static self) -> Result<AuthMsg, AfbError> {
+        let mut data_set = self.get_state()?;
+        if let AvailabilityState::Inoperative = data_set.availability {
+            return afb_error!("charger-inoperative", "authorization refused");
+        }
+        if data_set.relay.lockout {
+            return afb_error!("charger-relay-lockout", "authorization refused");
+        }
+        match data_set.auth {
+            AuthMsg::Pending | AuthMsg::Done => {}
+            _ => self.auth_rqt(&mut data_set)?,
+        }
+        Ok(data_set.auth)
+    }
+
+    // ocpp 2.0.1 RequestStartTransaction, CSMS id token replaces idp-login for this session
+    pub(crate) fn ocpp_remote_start(
+        &'static self,
+        mut data_set: MutexGuard<ChargingState>,
+        request: &Ocpp201RemoteStart,
+    ) -> Result<(), AfbError> {
+        if matches!(data_set.availability, AvailabilityState::Inoperative) || data_set.relay.lockout
+        {
+            afb_log_msg!(Warning, self.event, "ocpp remote start refused");
+            return Ok(());
+        }
+        if let AuthMsg::Pending | AuthMsg::Done = data_set.auth {
+            afb_log_msg!(
+                Warning,
+                self.event,
+                "ocpp remote start ignored, already authorized"
+            );
+            return Ok(());
+        }
+
+        let seq = self.auth_pending(&mut data_set)?;
+        data_set.session.remote_start = Some(request.clone());
+        let contract = AuthState {
+            auth: AuthMsg::Done,
+            tagid: request.id_token.id_token.clone(),
+            imax: self.contract_imax,
+            pmax: self.contract_pmax,
+            ocpp_check: self.ocpp_api.is_some(),
+            iso_only: false,
+        };
+        self.auth_contract(data_set, seq, &contract, None)
+    }
+
+    // start idp-login without blocking, the state lock is never held while waiting for auth/engy responses
+    pub(crate) fn auth_rqt(
+        &'static self,
+        data_set: &mut MutexGuard<ChargingState>,
+    ) -> Result<(), AfbError> {
+        afb_log_msg!(Notice, self.event, "Requesting idp-login");
+        let seq = self.auth_pending(data_set)?;
+
+        AfbSubCall::call_async(
+            self.apiv4,
+            self.auth_api,
+            "login",
+            AFB_NO_DATA,
+            auth_login_cb,
+            AuthLoginCtx { mgr: self, seq },
+        )?;
+        Ok(())
+    }
+
+    // open a new authorization sequence, fails after auth_timeout
+    pub(crate) fn auth_pending(
+        &'static self,
+        data_set: &mut MutexGuard<ChargingState>,
+    ) -> Result<u32, AfbError> {
+        data_set.auth_seq = data_set.auth_seq.wrapping_add(1);
+        data_set.auth_start = Some(Instant::now());
+        data_set.auth = AuthMsg::Pending;
+        self.event.push(ChargingMsg::Auth(data_set.auth));
+
+        AfbTimer::new("auth-timer")
+            .set_period(AUTH_PENDING_TIC)
+            .set_decount(self.auth_timeout / AUTH_PENDING_TIC + 1)
+            .set_callback(auth_timer_cb)
+            .set_context(AuthTimerCtx {
+                mgr: self,
+                seq: data_set.auth_seq,
+            })
+            .start()?;
+
+        Ok(data_set.auth_seq)
+    }
+
+    // session is over, drop any authorization held or still waiting for idp-login
+    pub(crate) fn auth_reset(&self, data_set: &mut MutexGuard<ChargingState>) {
+        data_set.auth_seq = data_set.auth_seq.wrapping_add(1);
+        data_set.auth_start = None;
+        data_set.auth_contract = None;
+        data_set.session.tagid = None;
+        data_set.session.remote_start = None;
+        if let AuthMsg::Idle = data_set.auth {
+            return;
+        }
+        afb_log_msg!(
+            Notice,
+            self.event,
+            "Reset idp-auth state:{:?}",
+            data_set.auth
+        );
+        data_set.auth = AuthMsg::Idle;
+        self.event.push(ChargingMsg::Auth(data_set.auth));
+    }
+
+    fn auth_tic(&self, seq: u32) -> Result<(), AfbError> {
+        let mut data_set = self.get_state()?;
+        if !auth_is_pending(&data_set, seq) {
+            return Ok(());
+        }
+
+        let elapsed = match data_set.auth_start {
+            Some(start) => start.elapsed().as_millis(),
+            None => 0,
+        };
+
+        if elapsed >= self.auth_timeout as u128 {
+            self.auth_reject(&mut data_set, "idp-login timeout")?;
+        } else {
+            self.event.push(ChargingMsg::Auth(data_set.auth));
+        }
+        Ok(())
+    }
+
+    fn auth_login_rsp(&'static self, seq: u32, contract: &AuthState) -> Result<(), AfbError> {
+        let mut data_set = self.get_state()?;
+        if !auth_is_pending(&data_set, seq) {
+            return Ok(());
+        }
+
+        if let AuthMsg::Fail = contract.auth {
+            return self.auth_reject(&mut data_set, "idp-login refused");
+        }
+        self.auth_contract(data_set, seq, contract, None)
+    }
+
+    // shared by idp-login and PnC, pnc contract is forwarded to ocpp with its certificate hash
+    pub(crate) fn auth_contract(
+        &'static self,
+        mut data_set: MutexGuard<ChargingState>,
+        seq: u32,
+        contract: &AuthState,
+        pnc: Option<&PncContract>,
+    ) -> Result<(), AfbError> {
+        // fleet contract forbids basic charging
+        if contract.iso_only && matches!(data_set.iso, IsoState::Iec) {
+            let info = format!("tagid:{} iso15118 only", contract.tagid);
+            return self.auth_reject(&mut data_set, &info);
+        }
+
+        // a reserved charger only accepts the reservation owner
+        let mismatch = match &data_set.reservation {
+            Some(resa) if resa.tagid != contract.tagid => Some(resa.id),
+            _ => None,
+        };
+        if let Some(resa_id) = mismatch {
+            let info = format!(
+                "tagid:{} not owner of reservation:{}",
+                contract.tagid, resa_id
+            );
+            return self.auth_reject(&mut data_set, &info);
+        }
+
+        data_set.session.tagid = Some(contract.tagid.clone());
+        data_set.session.ocpp_check = contract.ocpp_check;
+        data_set.session.pnc = pnc.is_some();
+        data_set.auth_contract = Some(contract.clone());
+
+        match self.ocpp_api {
+            Some(_) if contract.ocpp_check => {
+                // relay stays open until OcppMsg::Authorized verdict
+                afb_log_msg!(
+                    Notice,
+                    self.event,
+                    "Requesting ocpp authorize tagid:{}",
+                    contract.tagid
+                );
+                match pnc {
+                    Some(pnc) => self.ocpp_authorize_pnc(pnc),
+                    None => self.ocpp_authorize(&contract.tagid),
+                }
+            }
+            _ => {
+                // release state lock before requesting energy config
+                drop(data_set);
+                self.engy_config(seq, contract)
+            }
+        }
+    }
+
+    // called from ocpp event handler with state already locked
+    pub(crate) fn auth_ocpp_verdict(
+        &'static self,
+        data_set: &mut MutexGuard<ChargingState>,
+        allowed: bool,
+    ) -> Result<(), AfbError> {
+        let contract = match (&data_set.auth, &data_set.auth_contract) {
+            (AuthMsg::Pending, Some(contract)) if contract.ocpp_check => contract.clone(),
+            (AuthMsg::Done, _) if !allowed => return self.auth_revoke(data_set),
+            _ => {
+                afb_log_msg!(
+                    Debug,
+                    self.event,
+                    "Ignore unexpected ocpp authorize verdict"
+                );
+                return Ok(());
+            }
+        };
+
+        if !allowed {
+            return self.auth_reject(data_set, "ocpp authorize refused");
+        }
+        self.engy_config(data_set.auth_seq, &contract)
+    }
+
+    // CSMS withdrew authorization of a running session, transaction ends as DeAuthorized
+    fn auth_revoke(
+        &'static self,
+        data_set: &mut MutexGuard<ChargingState>,
+    ) -> Result<(), AfbError> {
+        afb_log_msg!(
+            Warning,
+            self.event,
+            "ocpp deauthorized tagid:{:?} stop power",
+            data_set.session.tagid
+        );
+        data_set.auth_seq = data_set.auth_seq.wrapping_add(1);
+        data_set.auth = AuthMsg::Fail;
+        self.event.push(ChargingMsg::Auth(data_set.auth));
+
+        // session record is published and cable unlocked once final energy is read
+        self.ocpp_stop(data_set, OcppStopReason::DeAuthorized)
+    }
+
+    fn engy_config(&'static self, seq: u32, contract: &AuthState) -> Result<(), AfbError> {
+        AfbSubCall::call_async(
+            self.apiv4,
+            self.engy_api,
+            "config",
+            EngyConfSet {
+                pmax: contract.pmax as i32,
+                imax: contract.imax as i32,
+            },
+            engy_conf_cb,
+            EngyConfCtx { mgr: self, seq },
+        )?;
+        Ok(())
+    }
+
+    fn auth_granted(&'static self, seq: u32, engy_conf: &EngyConfSet) -> Result<(), AfbError> {
+        let mut data_set = self.get_state()?;
+        if !auth_is_pending(&data_set, seq) {
+            return Ok(());
+        }
+
+        data_set.pmax = engy_conf.pmax as u32;
+        data_set.auth = AuthMsg::Done;
+        data_set.auth_start = None;
+        if let Some(resa) = data_set.reservation.take() {
+            data_set.session.reservation = Some(resa.id);
+        }
+        self.event.push(ChargingMsg::Auth(data_set.auth));
+        afb_log_msg!(Notice, self.event, "Valid idp-auth");
+        self.limit_set(
+            &mut data_set,
+            LimitSource::Contract,
+            Some(engy_conf.imax as u32),
+        )?;
+
+        if !is_plugged(&data_set) {
+            // tap-then-plug, hold authorization until the vehicle connects
+            afb_log_msg!(
+                Notice,
+                self.event,
+                "Hold idp-auth for {}ms waiting for plug-in",
+                self.auth_hold
+            );
+            AfbTimer::new("auth-hold")
+                .set_period(self.auth_hold)
+                .set_decount(1)
+                .set_callback(auth_hold_cb)
+                .set_context(AuthTimerCtx { mgr: self, seq })
+                .start()?;
+            return Ok(());
+        }
+
+        // plug-then-tap, slac already fell back to basic charging
+        self.auth_allow_power(&mut data_set)
+    }
+
+    pub(crate) fn auth_allow_power(
+        &'static self,
+        data_set: &mut MutexGuard<ChargingState>,
+    ) -> Result<(), AfbError> {
+        // cable is locked whatever the protocol, relay only closes once the lock is verified
+        self.cable_lock(data_set, true, false)
+    }
+
+    pub(crate) fn power_close(
+        &'static self,
+        data_set: &mut MutexGuard<ChargingState>,
+    ) -> Result<(), AfbError> {
+        // solar and scheduled modes wait for surplus or off-peak before closing the contactor
+        if data_set.solar_paused || data_set.tariff_hold {
+            afb_log_msg!(Notice, self.event, "Power held by mode:{:?}", data_set.mode);
+            return Ok(());
+        }
+        if data_set.thermal.cutoff {
+            afb_log_msg!(Warning, self.event, "Power held by high temperature");
+            return Ok(());
+        }
+        if data_set.relay.lockout {
+            afb_log_msg!(Error, self.event, "Power refused, relay lockout");
+            return Ok(());
+        }
+        if data_set.safety.running || data_set.safety.passed == Some(false) {
+            afb_log_msg!(Warning, self.event, "Power held by safety self-test");
+            return Ok(());
+        }
+        if self.lock.is_some() && !matches!(data_set.plugged, PlugState::Lock) {
+            afb_log_msg!(Warning, self.event, "Power held, cable not locked");
+            return Ok(());
+        }
+        // iso stack requests power through powerctrl, every resume path ends here
+        if !matches!(data_set.iso, IsoState::Iec) {
+            afb_log_msg!(
+                Debug,
+                self.event,
+                "Power left to iso stack iso:{:?}",
+                data_set.iso
+            );
+            return Ok(());
+        }
+        let imax = data_set.imax;
+
+        // set imax configuration and only close the contactor if we are in Basic Charging mode
+        AfbSubCall::call_async(
+            self.apiv4,
+            self.iec_api,
+            "imax",
+            imax,
+            ignore_rsp_cb,
+            IgnoreRspCtx {},
+        )?;
+        AfbSubCall::call_async(
+            self.apiv4,
+            self.iec_api,
+            "power",
+            true,
+            ignore_rsp_cb,
+            IgnoreRspCtx {},
+        )?;
+        self.event.push(ChargingMsg::Power(PowerRequest::Start));
+        afb_log_msg!(
+            Notice,
+            self.event,
+            "Slac+Auth done allow power iso_mode:{:?}",
+            data_set.iso
+        );
+        self.transaction_open(data_set)
+    }
+
+    // first power of the session, basic charging from power_close, iso from powerctrl
+    pub(crate) fn transaction_open(
+        &'static self,
+        data_set: &mut MutexGuard<ChargingState>,
+    ) -> Result<(), AfbError> {
+        self.transaction_start(data_set)?;
+        self.ocmf_open(data_set)
+    }
+
+    // tagid is forwarded to ocpp StartTransaction when idp requested an ocpp check
+    fn transaction_start(&self, data_set: &mut MutexGuard<ChargingState>) -> Result<(), AfbError> {
+        let tagid = match (&data_set.session.tagid, self.ocpp_api) {
+            (Some(tagid), Some(_)) if data_set.session.ocpp_check => tagid.clone(),
+            _ => return Ok(()),
+        };
+        if data_set.session.transaction {
+            return Ok(());
+        }
+
+        self.ocpp_transaction(&mut data_set.session, OcppTransaction::Start(tagid))?;
+        data_set.session.transaction = true;
+        Ok(())
+    }
+
+    // authorization held before plug-in was never consumed
+    fn auth_expire(&self, seq: u32) -> Result<(), AfbError> {
+        let mut data_set = self.get_state()?;
+        if data_set.auth_seq != seq
+            || !matches!(data_set.auth, AuthMsg::Done)
+            || is_plugged(&data_set)
+        {
+            return Ok(());
+        }
+
+        afb_log_msg!(Notice, self.event, "Held idp-auth expired without plug-in");
+        self.auth_reset(&mut data_set);
+        AfbSubCall::call_async(
+            self.apiv4,
+            self.auth_api,
+            "logout",
+            0,
+            ignore_rsp_cb,
+            IgnoreRspCtx {},
+        )?;
+        Ok(())
+    }
+
+    fn auth_fail(&self, seq: u32, info: &str) -> Result<(), AfbError> {
+        let mut data_set = self.get_state()?;
+        if !auth_is_pending(&data_set, seq) {
+            return Ok(());
+        }
+        self.auth_reject(&mut data_set, info)
+    }
+
+    pub(crate) fn auth_reject(
+        &self,
+        data_set: &mut MutexGuard<ChargingState>,
+        info: &str,
+    ) -> Result<(), AfbError> {
+        afb_log_msg!(Error, self.event, "fail idp authentication: {}", info);
+        data_set.auth = AuthMsg::Fail;
+        data_set.auth_start = None;
+        data_set.auth_contract = None;
+        data_set.session.tagid = None;
+        data_set.session.remote_start = None;
+        self.event.push(ChargingMsg::Auth(data_set.auth));
+        AfbSubCall::call_async(
+            self.apiv4,
+            self.iec_api,
+            "power",
+            false,
+            ignore_rsp_cb,
+            IgnoreRspCtx {},
+        )?;
+        Ok(())
+    }
+}
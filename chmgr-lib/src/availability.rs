@@ -0,0 +1,83 @@
+/*
+ * Copyright (C) 2015-2022 IoT.bzh Company
+ * Author: Fulup Ar Foll <fulup@iot.bzh>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *   http://www.apache.org/licenses/LICENSE-2.0
+ *
+ */
+
+use crate::ocpp::*;
+use crate::prelude::*;
+use afbv4::prelude::*;
+use std::sync::MutexGuard;
+use typesv4::prelude::*;
+
+impl ManagerHandle {
+    // local availability verb
+    pub fn availability(
+        &self,
+        request: OcppAvailability,
+    ) -> Result<OcppAvailabilityStatus, AfbError> {
+        let mut data_set = self.get_state()?;
+        self.availability_change(&mut data_set, request)
+    }
+
+    // running session is never interrupted, inoperative is deferred until unplug
+    pub(crate) fn availability_change(
+        &self,
+        data_set: &mut MutexGuard<ChargingState>,
+        request: OcppAvailability,
+    ) -> Result<OcppAvailabilityStatus, AfbError> {
+        let status = match request {
+            OcppAvailability::Operative => {
+                self.availability_apply(data_set, AvailabilityState::Operative)?;
+                OcppAvailabilityStatus::Accepted
+            }
+            OcppAvailability::Inoperative => {
+                if is_plugged(data_set) {
+                    data_set.availability = AvailabilityState::Scheduled;
+                    self.event
+                        .push(ChargingMsg::Availability(data_set.availability));
+                    OcppAvailabilityStatus::Scheduled
+                } else {
+                    self.availability_apply(data_set, AvailabilityState::Inoperative)?;
+                    OcppAvailabilityStatus::Accepted
+                }
+            }
+        };
+        Ok(status)
+    }
+
+    // inoperative keeps iec pwm in state A/F, plug-in is ignored until operative
+    pub(crate) fn availability_apply(
+        &self,
+        data_set: &mut MutexGuard<ChargingState>,
+        availability: AvailabilityState,
+    ) -> Result<(), AfbError> {
+        let previous = data_set.availability;
+        data_set.availability = availability;
+        self.event.push(ChargingMsg::Availability(availability));
+        afb_log_msg!(
+            Notice,
+            self.event,
+            "Charger availability {:?} => {:?}",
+            previous,
+            availability
+        );
+
+        AfbSubCall::call_async(
+            self.apiv4,
+            self.iec_api,
+            "available",
+            matches!(availability, AvailabilityState::Operative),
+            ignore_rsp_cb,
+            IgnoreRspCtx {},
+        )?;
+        self.ocpp_notify(ocpp_status(data_set))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,133 @@
+/*
+ * Copyright (C) 2015-2022 IoT.bzh Company
+ * Author: Fulup Ar Foll <fulup@iot.bzh>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *   http://www.apache.org/licenses/LICENSE-2.0
+ *
+ */
+
+use crate::prelude::*;
+use afbv4::prelude::*;
+use std::sync::Mutex;
+use typesv4::prelude::*;
+
+struct BudgetShare {
+    mgr: &'static ManagerHandle,
+    active: bool,
+}
+
+// site current (A) evenly split between charging connectors of one chmgr instance,
+// static budget_imax from config and available current (iavail) from energy manager
+pub struct PowerBudget {
+    imax: Option<u32>,
+    site: Mutex<Option<u32>>,
+    shares: Mutex<Vec<BudgetShare>>,
+}
+
+impl PowerBudget {
+    pub fn new(imax: Option<u32>) -> &'static Self {
+        Box::leak(Box::new(PowerBudget {
+            imax,
+            site: Mutex::new(None),
+            shares: Mutex::new(Vec::new()),
+        }))
+    }
+
+    // (connector, budget share, site share) for every connector
+    fn split(
+        &self,
+        shares: &[BudgetShare],
+    ) -> Vec<(&'static ManagerHandle, Option<u32>, Option<u32>)> {
+        let count = shares.iter().filter(|share| share.active).count().max(1) as u32;
+        let site = *self.site.lock().unwrap();
+        shares
+            .iter()
+            .map(|share| {
+                (
+                    share.mgr,
+                    self.imax.map(|imax| imax / count),
+                    site.map(|imax| imax / count),
+                )
+            })
+            .collect()
+    }
+
+    pub fn add_connector(&self, mgr: &'static ManagerHandle) {
+        let mut shares = self.shares.lock().unwrap();
+        shares.push(BudgetShare { mgr, active: false });
+    }
+
+    // caller must not hold any connector state lock, every connector is updated
+    pub(crate) fn update(&self, connector_id: u32, active: bool) -> Result<(), AfbError> {
+        let updates = {
+            let mut shares = self.shares.lock().unwrap();
+            let mut changed = false;
+            for share in shares.iter_mut() {
+                if share.mgr.connector_id == connector_id && share.active != active {
+                    share.active = active;
+                    changed = true;
+                }
+            }
+            if !changed {
+                return Ok(());
+            }
+            self.split(&shares)
+        };
+
+        for (mgr, budget, site) in updates {
+            mgr.budget_imax(budget, site)?;
+        }
+        Ok(())
+    }
+
+    // same rule as update, caller must not hold any connector state lock
+    pub(crate) fn site_update(&self, imax: u32) -> Result<(), AfbError> {
+        let updates = {
+            let shares = self.shares.lock().unwrap();
+            let mut site = self.site.lock().unwrap();
+            if *site == Some(imax) {
+                return Ok(());
+            }
+            *site = Some(imax);
+            drop(site);
+            self.split(&shares)
+        };
+
+        for (mgr, budget, site) in updates {
+            mgr.budget_imax(budget, site)?;
+        }
+        Ok(())
+    }
+}
+
+impl ManagerHandle {
+    fn budget_imax(&self, budget: Option<u32>, site: Option<u32>) -> Result<(), AfbError> {
+        let mut data_set = self.get_state()?;
+        if data_set.limits.budget == budget && data_set.limits.site == site {
+            return Ok(());
+        }
+        afb_log_msg!(
+            Notice,
+            self.event,
+            "connector:{} budget imax:{:?} site imax:{:?}",
+            self.connector_id,
+            budget,
+            site
+        );
+        data_set.limits.budget = budget;
+        data_set.limits.site = site;
+        self.limit_update(&mut data_set)
+    }
+
+    // called once iec event is processed and state lock released
+    pub(crate) fn budget_update(&self, active: bool) -> Result<(), AfbError> {
+        match self.budget {
+            Some(budget) => budget.update(self.connector_id, active),
+            None => Ok(()),
+        }
+    }
+}
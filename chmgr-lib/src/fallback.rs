@@ -0,0 +1,105 @@
+/*
+ * Copyright (C) 2015-2022 IoT.bzh Company
+ * Author: Fulup Ar Foll <fulup@iot.bzh>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *   http://www.apache.org/licenses/LICENSE-2.0
+ *
+ */
+
+use crate::prelude::*;
+use afbv4::prelude::*;
+use std::sync::MutexGuard;
+use typesv4::prelude::*;
+
+impl ManagerHandle {
+    // iso stack failure after SLAC match (iso-fallback verb)
+    pub fn iso_fallback(&'static self, reason: FallbackReason) -> Result<(), AfbError> {
+        let mut data_set = self.get_state()?;
+        match data_set.iso {
+            IsoState::Iso2 | IsoState::Iso20 | IsoState::Iso20Discharge | IsoState::Iso3 => {
+                self.iec_fallback(&mut data_set, reason)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn fallback_allowed(&self, data_set: &ChargingState, reason: FallbackReason) -> bool {
+        if !self.basic_charging_enabled {
+            return false;
+        }
+        if let AvailabilityState::Inoperative = data_set.availability {
+            return false;
+        }
+        if let Some(contract) = &data_set.auth_contract {
+            if contract.iso_only {
+                return false;
+            }
+        }
+        match reason {
+            FallbackReason::SlacTimeout => self.fallback.slac_timeout,
+            FallbackReason::IsoFailure => self.fallback.iso_failure,
+            FallbackReason::V2gTimeout => self.fallback.v2g_timeout,
+        }
+    }
+
+    pub(crate) fn iec_fallback(
+        &'static self,
+        data_set: &mut MutexGuard<ChargingState>,
+        reason: FallbackReason,
+    ) -> Result<(), AfbError> {
+        if !self.fallback_allowed(data_set, reason) {
+            afb_log_msg!(Warning, self.event, "no iec fallback reason:{:?}", reason);
+            return Ok(());
+        }
+        afb_log_msg!(Notice, self.event, "iec fallback reason:{:?}", reason);
+
+        if let Some(slac_api) = self.slac_api {
+            AfbSubCall::call_async(
+                self.apiv4,
+                slac_api,
+                "stop",
+                AFB_NO_DATA,
+                ignore_rsp_cb,
+                IgnoreRspCtx {},
+            )?;
+        }
+
+        // firmware was already told on SLAC failure, iso failures come after a match
+        if !matches!(reason, FallbackReason::SlacTimeout) {
+            AfbSubCall::call_async(
+                self.apiv4,
+                self.iec_api,
+                "slac",
+                SlacStatus::TIMEOUT,
+                ignore_rsp_cb,
+                IgnoreRspCtx {},
+            )?;
+        }
+
+        self.v2g_stop(data_set, "iec fallback")?;
+        data_set.iso = IsoState::Iec;
+        data_set.iso_param = None;
+        self.event.push(ChargingMsg::Iso(data_set.iso));
+
+        match (reason, data_set.auth) {
+            // tap-then-plug, authorization is already held
+            (FallbackReason::SlacTimeout, AuthMsg::Done) => self.auth_allow_power(data_set),
+            // tap already in progress, power is allowed from auth_granted
+            (FallbackReason::SlacTimeout, AuthMsg::Pending) => Ok(()),
+            // iso authorization is meaningless for basic charging, restart idp-login
+            (FallbackReason::SlacTimeout, _) => self.auth_rqt(data_set),
+            // iso session may have been charging, relay opens before basic charging auth
+            _ => {
+                self.relay_open(data_set)?;
+                data_set.power = PowerRequest::Idle;
+                self.event.push(ChargingMsg::Power(data_set.power));
+                self.auth_reset(data_set);
+                self.auth_rqt(data_set)
+            }
+        }
+    }
+}
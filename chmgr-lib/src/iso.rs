@@ -0,0 +1,147 @@
+/*
+ * Copyright (C) 2015-2022 IoT.bzh Company
+ * Author: Fulup Ar Foll <fulup@iot.bzh>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *   http://www.apache.org/licenses/LICENSE-2.0
+ *
+ */
+
+use crate::limit::*;
+use crate::phase::*;
+use crate::prelude::*;
+use afbv4::prelude::*;
+use std::sync::MutexGuard;
+use std::time::Duration;
+use typesv4::prelude::*;
+
+// imax applies on every active phase, contract pmax is expressed in kW, iso15118 expects W
+fn iso_schedule_entry(data_set: &ChargingState, start: u32, imax: u32) -> IsoScheduleEntry {
+    let mut pmax = imax * data_set.phases * PHASE_VOLTAGE;
+    if data_set.pmax > 0 {
+        pmax = pmax.min(data_set.pmax * 1000);
+    }
+    IsoScheduleEntry { start, imax, pmax }
+}
+
+// schedule start moves with elapsed time, only limit values trigger a renegotiation
+fn iso_param_changed(previous: &IsoChargeParam, param: &IsoChargeParam) -> bool {
+    previous.imax != param.imax
+        || previous.pmax != param.pmax
+        || previous.setpoint != param.setpoint
+        || previous.discharge_imax != param.discharge_imax
+        || previous.discharge_pmax != param.discharge_pmax
+        || previous.schedule.len() != param.schedule.len()
+}
+
+impl ManagerHandle {
+    // iso stack request for charge parameters (iso-param verb)
+    pub fn iso_charge_param(&self) -> Result<IsoChargeParam, AfbError> {
+        let mut data_set = self.get_state()?;
+        let param = self.iso_param_build(&data_set);
+        data_set.iso_param = Some(param.clone());
+        Ok(param)
+    }
+
+    // EV data from iso stack (ev-info verb), fields missing in update keep their last value
+    pub fn set_ev_info(&'static self, info: &EvInfo) -> Result<(), AfbError> {
+        let mut data_set = self.get_state()?;
+        let mut ev = data_set.ev.take().unwrap_or_default();
+        if info.evccid.is_some() {
+            ev.evccid = info.evccid.clone();
+        }
+        if info.mac.is_some() {
+            ev.mac = info.mac.clone();
+        }
+        if info.energy_request.is_some() {
+            ev.energy_request = info.energy_request;
+        }
+        if let Some(departure) = info.departure {
+            ev.departure = Some(departure);
+            data_set.departure = Some(get_timestamp() + Duration::from_secs(departure as u64));
+        }
+        if info.soc.is_some() {
+            ev.soc = info.soc;
+        }
+        if info.imax.is_some() {
+            ev.imax = info.imax;
+        }
+
+        data_set.ev = Some(ev.clone());
+        data_set.session.ev = Some(ev.clone());
+        self.event.push(ChargingMsg::EvInfo(ev));
+        self.v2g_check(&mut data_set)?;
+        self.tariff_check(&mut data_set)?;
+        self.iso_renegotiate(&mut data_set)
+    }
+
+    // arbiter limits without ocpp profile, capped by EV, ocpp limit ends after its duration
+    fn iso_param_build(&self, data_set: &ChargingState) -> IsoChargeParam {
+        let (mut imax, _) = limit_min(&data_set.limits, false);
+        if let Some(ev_imax) = data_set.ev.as_ref().and_then(|ev| ev.imax) {
+            imax = imax.min(ev_imax);
+        }
+
+        let mut schedule = Vec::new();
+        match data_set.ocpp_limit {
+            Some((limit, received)) if limit.imax >= 0 => {
+                let elapsed = get_timestamp().saturating_sub(received).as_secs() as u32;
+                let ocpp_imax = imax.min(limit.imax as u32);
+                if limit.duration == 0 {
+                    schedule.push(iso_schedule_entry(data_set, 0, ocpp_imax));
+                } else if elapsed < limit.duration {
+                    schedule.push(iso_schedule_entry(data_set, 0, ocpp_imax));
+                    schedule.push(iso_schedule_entry(data_set, limit.duration - elapsed, imax));
+                } else {
+                    schedule.push(iso_schedule_entry(data_set, 0, imax));
+                }
+            }
+            _ => schedule.push(iso_schedule_entry(data_set, 0, imax)),
+        }
+
+        let (discharge_imax, discharge_pmax) = match &self.v2g {
+            Some(config) => (config.imax, config.pmax * 1000),
+            None => (0, 0),
+        };
+
+        IsoChargeParam {
+            imax: schedule[0].imax,
+            pmax: schedule[0].pmax,
+            vmax: self.iso_vmax,
+            schedule,
+            setpoint: data_set.setpoint,
+            discharge_imax,
+            discharge_pmax,
+        }
+    }
+
+    // push new parameters when limits changed after the iso stack fetched them
+    pub(crate) fn iso_renegotiate(
+        &self,
+        data_set: &mut MutexGuard<ChargingState>,
+    ) -> Result<(), AfbError> {
+        let previous = match &data_set.iso_param {
+            Some(value) => value,
+            None => return Ok(()),
+        };
+
+        let param = self.iso_param_build(data_set);
+        if !iso_param_changed(previous, &param) {
+            return Ok(());
+        }
+
+        afb_log_msg!(
+            Notice,
+            self.event,
+            "iso renegotiate imax:{} pmax:{}",
+            param.imax,
+            param.pmax
+        );
+        data_set.iso_param = Some(param.clone());
+        self.event.push(ChargingMsg::IsoParam(param));
+        Ok(())
+    }
+}
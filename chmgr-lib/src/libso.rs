@@ -21,6 +21,64 @@ extern crate afbv4;
 #[path = "manager.rs"]
 mod manager;
 
+#[path = "auth.rs"]
+mod auth;
+
+#[path = "ocpp.rs"]
+mod ocpp;
+
+#[path = "availability.rs"]
+mod availability;
+
+#[path = "lock.rs"]
+mod lock;
+
+#[path = "iso.rs"]
+mod iso;
+
+#[path = "v2g.rs"]
+mod v2g;
+
+#[path = "pnc.rs"]
+mod pnc;
+
+#[path = "slac.rs"]
+mod slac;
+
+#[path = "fallback.rs"]
+mod fallback;
+
+#[path = "budget.rs"]
+mod budget;
+
+#[path = "phase.rs"]
+mod phase;
+
+#[path = "solar.rs"]
+mod solar;
+
+#[path = "mode.rs"]
+mod mode;
+
+#[path = "limit.rs"]
+mod limit;
+
+#[path = "thermal.rs"]
+mod thermal;
+
+#[path = "safety.rs"]
+mod safety;
+
+#[path = "relay.rs"]
+mod relay;
+
+#[path = "meter.rs"]
+mod meter;
+
+#[path = "ocmf.rs"]
+mod ocmf;
+
 pub mod prelude {
+    pub use crate::budget::*;
     pub use crate::manager::*;
 }
\ No newline at end of file
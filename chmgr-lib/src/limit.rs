@@ -0,0 +1,169 @@
+/*
+ * Copyright (C) 2015-2022 IoT.bzh Company
+ * Author: Fulup Ar Foll <fulup@iot.bzh>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *   http://www.apache.org/licenses/LICENSE-2.0
+ *
+ */
+
+use crate::meter::*;
+use crate::prelude::*;
+use afbv4::prelude::*;
+use std::sync::MutexGuard;
+use std::time::Duration;
+use typesv4::prelude::*;
+
+// applied until a first source gives a value
+const IMAX_DEFAULT: u32 = 32;
+
+struct LimitExpireCtx {
+    mgr: &'static ManagerHandle,
+    received: Duration,
+}
+
+fn limit_expire_cb(_timer: &AfbTimer, _decount: u32, ctx: &AfbCtxData) -> Result<(), AfbError> {
+    let ctx = ctx.get_ref::<LimitExpireCtx>()?;
+    ctx.mgr.limit_expire(ctx.received)
+}
+
+fn limit_slot(limits: &mut ImaxLimits, source: LimitSource) -> &mut Option<u32> {
+    match source {
+        LimitSource::Cable => &mut limits.cable,
+        LimitSource::Contract => &mut limits.contract,
+        LimitSource::Ocpp => &mut limits.ocpp,
+        LimitSource::Site => &mut limits.site,
+        LimitSource::Budget => &mut limits.budget,
+        LimitSource::Thermal => &mut limits.thermal,
+        LimitSource::Mode => &mut limits.mode,
+        LimitSource::Fault => &mut limits.fault,
+    }
+}
+
+// lowest constraint and its source, iso schedule handles ocpp profile itself
+pub(crate) fn limit_min(limits: &ImaxLimits, ocpp: bool) -> (u32, Option<LimitSource>) {
+    let sources = [
+        (LimitSource::Cable, limits.cable),
+        (LimitSource::Contract, limits.contract),
+        (LimitSource::Ocpp, if ocpp { limits.ocpp } else { None }),
+        (LimitSource::Site, limits.site),
+        (LimitSource::Budget, limits.budget),
+        (LimitSource::Thermal, limits.thermal),
+        (LimitSource::Mode, limits.mode),
+        (LimitSource::Fault, limits.fault),
+    ];
+
+    let mut result = (IMAX_DEFAULT, None);
+    for (source, value) in sources {
+        if let Some(value) = value {
+            if result.1.is_none() || value < result.0 {
+                result = (value, Some(source));
+            }
+        }
+    }
+    result
+}
+
+impl ManagerHandle {
+    // ocpp profile is released by a timer at its expiry, duration 0 never expires
+    pub(crate) fn limit_profile(
+        &'static self,
+        data_set: &mut MutexGuard<ChargingState>,
+        limit: PowerLimit,
+    ) -> Result<(), AfbError> {
+        let received = get_timestamp();
+        data_set.ocpp_limit = Some((limit, received));
+        if limit.duration > 0 {
+            AfbTimer::new("limit-expire")
+                .set_period(limit.duration.saturating_mul(1000))
+                .set_decount(1)
+                .set_callback(limit_expire_cb)
+                .set_context(LimitExpireCtx {
+                    mgr: self,
+                    received,
+                })
+                .start()?;
+        }
+        self.limit_update(data_set)
+    }
+
+    // a newer profile restarted its own timer
+    fn limit_expire(&self, received: Duration) -> Result<(), AfbError> {
+        let mut data_set = self.get_state()?;
+        match data_set.ocpp_limit {
+            Some((_, value)) if value == received => {
+                afb_log_msg!(Notice, self.event, "ocpp power limit expired");
+                data_set.ocpp_limit = None;
+                self.limit_update(&mut data_set)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // every imax writer goes through the arbiter, result does not depend on event order
+    pub(crate) fn limit_set(
+        &self,
+        data_set: &mut MutexGuard<ChargingState>,
+        source: LimitSource,
+        value: Option<u32>,
+    ) -> Result<(), AfbError> {
+        *limit_slot(&mut data_set.limits, source) = value;
+        self.limit_update(data_set)
+    }
+
+    // ocpp and mode sources are derived from their own state, iec imax is only written on change
+    pub(crate) fn limit_update(
+        &self,
+        data_set: &mut MutexGuard<ChargingState>,
+    ) -> Result<(), AfbError> {
+        data_set.limits.ocpp = match data_set.ocpp_limit {
+            Some((limit, received)) if limit.imax >= 0 => {
+                let elapsed = get_timestamp().saturating_sub(received).as_secs() as u32;
+                if limit.duration == 0 || elapsed < limit.duration {
+                    Some(limit.imax as u32)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+        data_set.limits.mode = if data_set.tariff_hold {
+            Some(0)
+        } else {
+            data_set.solar_imax
+        };
+
+        let (imax, source) = limit_min(&data_set.limits, true);
+        data_set.imax_source = source;
+        if imax != data_set.imax {
+            afb_log_msg!(
+                Notice,
+                self.event,
+                "connector:{} imax:{} source:{:?}",
+                self.connector_id,
+                imax,
+                source
+            );
+            data_set.imax = imax;
+            meter_settle(data_set);
+            if matches!(data_set.iso, IsoState::Iec) {
+                AfbSubCall::call_async(
+                    self.apiv4,
+                    self.iec_api,
+                    "imax",
+                    imax,
+                    ignore_rsp_cb,
+                    IgnoreRspCtx {},
+                )?;
+            }
+            if let PowerRequest::Charging(_) = data_set.power {
+                data_set.power = PowerRequest::Charging(imax);
+                self.event.push(ChargingMsg::Power(data_set.power));
+            }
+        }
+        self.iso_renegotiate(data_set)
+    }
+}
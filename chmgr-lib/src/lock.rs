@@ -0,0 +1,169 @@
+/*
+ * Copyright (C) 2015-2022 IoT.bzh Company
+ * Author: Fulup Ar Foll <fulup@iot.bzh>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *   http://www.apache.org/licenses/LICENSE-2.0
+ *
+ */
+
+use crate::ocpp::*;
+use crate::prelude::*;
+use afbv4::prelude::*;
+use std::sync::MutexGuard;
+use typesv4::prelude::*;
+
+struct LockCtx {
+    mgr: &'static ManagerHandle,
+    lock: bool,
+    report: bool,
+}
+
+fn lock_cmd_cb(_api: &AfbApi, _args: &AfbRqtData, ctx: &AfbCtxData) -> Result<(), AfbError> {
+    let ctx = ctx.get_ref::<LockCtx>()?;
+    // motor command result is not trusted, lock-status register is
+    ctx.mgr.cable_lock_check(ctx.lock, ctx.report)
+}
+
+fn lock_status_cb(_api: &AfbApi, args: &AfbRqtData, ctx: &AfbCtxData) -> Result<(), AfbError> {
+    let ctx = ctx.get_ref::<LockCtx>()?;
+
+    let status = match args.get::<u32>(0) {
+        Ok(value) => Some(value),
+        Err(error) => {
+            afb_log_msg!(
+                Error,
+                None,
+                "cable lock-status read fail:{}",
+                error.get_info()
+            );
+            None
+        }
+    };
+    ctx.mgr.cable_lock_done(ctx.lock, ctx.report, status)
+}
+
+impl ManagerHandle {
+    pub(crate) fn cable_lock(
+        &'static self,
+        data_set: &mut MutexGuard<ChargingState>,
+        lock: bool,
+        report: bool,
+    ) -> Result<(), AfbError> {
+        // without lock_api, cable lock stays under iec firmware control
+        let config = match &self.lock {
+            Some(value) => value,
+            None if lock => return self.power_close(data_set),
+            None => return self.cable_lock_report(report, true),
+        };
+
+        let action = if lock { "on" } else { "off" };
+        afb_log_msg!(Debug, self.event, "cable lock request action:{}", action);
+        AfbSubCall::call_async(
+            self.apiv4,
+            config.api,
+            config.verb,
+            action,
+            lock_cmd_cb,
+            LockCtx {
+                mgr: self,
+                lock,
+                report,
+            },
+        )?;
+        Ok(())
+    }
+
+    pub(crate) fn cable_unlock(
+        &'static self,
+        data_set: &mut MutexGuard<ChargingState>,
+        report: bool,
+    ) -> Result<(), AfbError> {
+        self.cable_lock(data_set, false, report)
+    }
+
+    fn cable_lock_check(&'static self, lock: bool, report: bool) -> Result<(), AfbError> {
+        let config = match &self.lock {
+            Some(value) => value,
+            None => return Ok(()),
+        };
+
+        AfbSubCall::call_async(
+            self.apiv4,
+            config.api,
+            config.status_verb,
+            AFB_NO_DATA,
+            lock_status_cb,
+            LockCtx {
+                mgr: self,
+                lock,
+                report,
+            },
+        )?;
+        Ok(())
+    }
+
+    fn cable_lock_done(
+        &'static self,
+        lock: bool,
+        report: bool,
+        status: Option<u32>,
+    ) -> Result<(), AfbError> {
+        let mut data_set = self.get_state()?;
+        let success = match (&self.lock, status) {
+            (Some(config), Some(value)) => (value & config.mask != 0) == lock,
+            _ => false,
+        };
+        self.cable_lock_apply(&mut data_set, lock, report, success)
+    }
+
+    fn cable_lock_apply(
+        &'static self,
+        data_set: &mut MutexGuard<ChargingState>,
+        lock: bool,
+        report: bool,
+        success: bool,
+    ) -> Result<(), AfbError> {
+        if !success {
+            afb_log_msg!(Error, self.event, "cable lock:{} verification failed", lock);
+            fault_set(data_set, OcppErrorCode::ConnectorLockFailure);
+            data_set.power = PowerRequest::Idle;
+            self.event.push(ChargingMsg::Plugged(PlugState::Error));
+            self.ocpp_notify(ocpp_status(data_set))?;
+        } else if lock {
+            // session may have been cancelled while the motor was running
+            if !is_plugged(data_set) || !matches!(data_set.auth, AuthMsg::Done) {
+                return Ok(());
+            }
+            data_set.plugged = PlugState::Lock;
+            self.event.push(ChargingMsg::Plugged(data_set.plugged));
+            self.power_close(data_set)?;
+        } else {
+            if let PlugState::Lock = data_set.plugged {
+                data_set.plugged = PlugState::PlugIn;
+                self.event.push(ChargingMsg::Plugged(data_set.plugged));
+            }
+            fault_clear(data_set, OcppErrorCode::ConnectorLockFailure);
+        }
+
+        self.cable_lock_report(report, success && !lock)
+    }
+
+    // ocpp UnlockConnector expects Unlocked/UnlockFailed
+    fn cable_lock_report(&self, report: bool, unlocked: bool) -> Result<(), AfbError> {
+        if let (true, Some(ocpp_api)) = (report, self.ocpp_api) {
+            AfbSubCall::call_async(
+                self.apiv4,
+                ocpp_api,
+                "unlock-done",
+                unlocked,
+                ignore_rsp_cb,
+                IgnoreRspCtx {},
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -10,25 +10,249 @@
  *
  */
 
+use crate::meter::*;
+use crate::phase::*;
+use crate::solar::*;
+use crate::v2g::*;
 use afbv4::prelude::*;
+use std::mem::discriminant;
 use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use typesv4::prelude::*;
 
-pub struct ManagerHandle {
-    apiv4: AfbApiV4,
-    data_set: Mutex<ChargingState>,
-    auth_api: &'static str,
-    iec_api: &'static str,
-    engy_api: &'static str,
-    ocpp_api: Option<&'static str>,
-    event: &'static AfbEvent,
+// cable lock motor and lock-status register (i2c gpio)
+#[derive(Clone)]
+pub struct CableLockConfig {
+    pub api: &'static str,
+    pub verb: &'static str,
+    pub status_verb: &'static str,
+    pub mask: u32,
+}
+
+// bidirectional charging limits, discharge stops when EV SoC reaches soc_min (%)
+#[derive(Clone)]
+pub struct V2gConfig {
+    pub imax: u32,
+    pub pmax: u32,
+    pub soc_min: u32,
+}
+
+// contactor supervision, relay-status register (i2c) is read delay (ms) after each switch,
+// current (mA) above which an open relay is considered welded
+#[derive(Clone)]
+pub struct RelayConfig {
+    pub api: &'static str,
+    pub status_verb: &'static str,
+    pub mask: u32,
+    pub delay: u32,
+    pub current: i32,
+}
+
+// 1p/3p contactor, pause (ms) in state B before switching, delay (ms) between two switches
+#[derive(Clone)]
+pub struct PhaseConfig {
+    pub api: &'static str,
+    pub verb: &'static str,
+    pub pause: u32,
+    pub delay: u32,
+}
+
+// grid power source for PV surplus charging, smoothing is the moving average weight (%)
+#[derive(Clone)]
+pub struct SolarConfig {
+    pub api: &'static str,
+    pub verb: &'static str,
+    pub smoothing: u32,
+    // guaranteed current (A) in min+solar hybrid mode
+    pub hybrid_imin: u32,
+    // time (ms) surplus may stay under 6A before pausing
+    pub pause_delay: u32,
+}
+
+// off-peak window for scheduled mode (minutes of the day), utc_offset in minutes
+#[derive(Clone)]
+pub struct TariffConfig {
+    pub offpeak_start: u32,
+    pub offpeak_stop: u32,
+    pub utc_offset: i32,
+}
+
+// derating curve point, imax (A) applied at temperature (°C)
+#[derive(Clone)]
+pub struct ThermalPoint {
+    pub temperature: i32,
+    pub imax: u32,
+}
+
+// temperature sensors, charge stops above cutoff (°C) until it drops under cutoff - hysteresis
+#[derive(Clone)]
+pub struct ThermalConfig {
+    pub api: &'static str,
+    pub verb: &'static str,
+    pub curve: Vec<ThermalPoint>,
+    pub cutoff: i32,
+    pub hysteresis: i32,
+}
+
+// iec firmware self-tests, run at plug-in and/or every period (ms) while connector is free
+#[derive(Clone)]
+pub struct SafetyConfig {
+    pub tests: Vec<SafetyTest>,
+    pub on_plugin: bool,
+    pub period: u32,
+}
+
+// meter plausibility, readings older than stale (ms) are a failure, tolerance (A) above imax,
+// idle (mA) max current with relay open
+#[derive(Clone)]
+pub struct MeterConfig {
+    pub stale: u32,
+    pub tolerance: u32,
+    pub idle: i32,
+}
+
+// vendor DataTransfer requests are routed to handler registered at binding init,
+// handler runs without state lock and drives the manager as any verb does
+pub trait DataTransferHandler: Send + Sync {
+    fn transfer(
+        &self,
+        mgr: &'static ManagerHandle,
+        request: &OcppDataTransfer,
+    ) -> Result<OcppDataTransferRsp, AfbError>;
+}
+
+// events allowed to abandon iso15118 for basic charging
+#[derive(Clone)]
+pub struct FallbackPolicy {
+    pub slac_timeout: bool,
+    pub iso_failure: bool,
+    pub v2g_timeout: bool,
+}
+
+// PnC contract chain validation against local trust store and local authorization list
+pub trait ContractValidator: Send + Sync {
+    fn validate(&self, contract: &PncContract) -> Result<(), AfbError>;
+    fn is_listed(&self, emaid: &str) -> bool;
+}
+
+pub struct ManagerConfig {
+    pub auth_api: &'static str,
+    pub iec_api: &'static str,
+    pub engy_api: &'static str,
+    pub ocpp_api: Option<&'static str>,
+    pub slac_api: Option<&'static str>,
+    // SLAC restarts on UNMATCHED before falling back to basic charging
+    pub slac_retry: u32,
+    pub ocpp_version: OcppVersion,
+    pub evse_id: u32,
+    pub connector_id: u32,
+    pub lock: Option<CableLockConfig>,
+    pub relay: Option<RelayConfig>,
+    pub phase: Option<PhaseConfig>,
+    pub solar: Option<SolarConfig>,
+    pub tariff: Option<TariffConfig>,
+    pub thermal: Option<ThermalConfig>,
+    pub safety: Option<SafetyConfig>,
+    pub meter: Option<MeterConfig>,
+    // energy api verb returning OCMF signed readings at transaction start/stop
+    pub ocmf: Option<&'static str>,
+    // max voltage (V) announced to the iso15118 stack
+    pub iso_vmax: u32,
+    pub v2g: Option<V2gConfig>,
+    // site current shared with the other connectors of this instance
+    pub budget: Option<&'static PowerBudget>,
     // For (iso15118) debugging purpose, basic charging can be disabled
-    basic_charging_enabled: bool,
+    pub basic_charging_enabled: bool,
+    pub fallback: FallbackPolicy,
+    // max time (ms) granted to idp-login before failing authentication
+    pub auth_timeout: u32,
+    // time (ms) an authorization obtained before plug-in is held waiting for the vehicle
+    pub auth_hold: u32,
+    // current (A) and power (kW) granted to contracts not issued by idp (PnC, ocpp remote start)
+    pub contract_imax: u32,
+    pub contract_pmax: u32,
+}
+
+pub struct ManagerHandle {
+    pub(crate) apiv4: AfbApiV4,
+    pub(crate) data_set: Mutex<ChargingState>,
+    pub(crate) auth_api: &'static str,
+    pub(crate) iec_api: &'static str,
+    pub(crate) engy_api: &'static str,
+    pub(crate) ocpp_api: Option<&'static str>,
+    pub(crate) slac_api: Option<&'static str>,
+    pub(crate) slac_retry: u32,
+    pub(crate) ocpp_version: OcppVersion,
+    pub(crate) evse_id: u32,
+    pub(crate) connector_id: u32,
+    pub(crate) lock: Option<CableLockConfig>,
+    pub(crate) relay: Option<RelayConfig>,
+    pub(crate) phase: Option<PhaseConfig>,
+    pub(crate) solar: Option<SolarConfig>,
+    pub(crate) tariff: Option<TariffConfig>,
+    pub(crate) thermal: Option<ThermalConfig>,
+    pub(crate) safety: Option<SafetyConfig>,
+    pub(crate) meter: Option<MeterConfig>,
+    pub(crate) ocmf: Option<&'static str>,
+    pub(crate) iso_vmax: u32,
+    pub(crate) v2g: Option<V2gConfig>,
+    pub(crate) budget: Option<&'static PowerBudget>,
+    pub(crate) data_transfer: Option<(&'static str, Box<dyn DataTransferHandler>)>,
+    pub(crate) contract_validator: Option<Box<dyn ContractValidator>>,
+    pub(crate) event: &'static AfbEvent,
+    pub(crate) basic_charging_enabled: bool,
+    pub(crate) fallback: FallbackPolicy,
+    pub(crate) auth_timeout: u32,
+    pub(crate) auth_hold: u32,
+    pub(crate) contract_imax: u32,
+    pub(crate) contract_pmax: u32,
+}
+
+// session record timestamps are expressed since epoch
+pub(crate) fn get_timestamp() -> Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
 }
 
-struct IgnoreRspCtx {}
+pub(crate) fn is_plugged(data_set: &ChargingState) -> bool {
+    matches!(data_set.plugged, PlugState::PlugIn | PlugState::Lock)
+}
+
+// relay lockout and thermal cutoff own the connector fault until they are released
+fn fault_owned(data_set: &ChargingState) -> bool {
+    data_set.relay.lockout || data_set.thermal.cutoff
+}
+
+pub(crate) fn fault_set(data_set: &mut ChargingState, code: OcppErrorCode) {
+    if !fault_owned(data_set) {
+        data_set.fault = Some(code);
+    }
+}
+
+// only clears the fault raised by the same source
+pub(crate) fn fault_clear(data_set: &mut ChargingState, code: OcppErrorCode) {
+    let same = match &data_set.fault {
+        Some(fault) => discriminant(fault) == discriminant(&code),
+        None => false,
+    };
+    if same && !fault_owned(data_set) {
+        data_set.fault = None;
+    }
+}
+
+// authorized session with vehicle still connected, relay may be closed again after a pause
+pub(crate) fn session_active(data_set: &ChargingState) -> bool {
+    matches!(data_set.auth, AuthMsg::Done) && is_plugged(data_set)
+}
 
-fn ignore_rsp_cb(_api: &AfbApi, _args: &AfbRqtData, _ctx: &AfbCtxData) -> Result<(), AfbError> {
+pub(crate) struct IgnoreRspCtx {}
+
+pub(crate) fn ignore_rsp_cb(
+    _api: &AfbApi,
+    _args: &AfbRqtData,
+    _ctx: &AfbCtxData,
+) -> Result<(), AfbError> {
     let _ctx = _ctx.get_ref::<IgnoreRspCtx>()?;
 
     Ok(())
@@ -37,28 +261,65 @@ fn ignore_rsp_cb(_api: &AfbApi, _args: &AfbRqtData, _ctx: &AfbCtxData) -> Result
 impl ManagerHandle {
     pub fn new(
         apiv4: AfbApiV4,
-        auth_api: &'static str,
-        iec_api: &'static str,
-        engy_api: &'static str,
-        ocpp_api: Option<&'static str>,
+        config: ManagerConfig,
         event: &'static AfbEvent,
-        basic_charging_enabled: bool,
     ) -> &'static mut Self {
         let handle = ManagerHandle {
             apiv4,
-            auth_api,
-            iec_api,
-            engy_api,
-            ocpp_api,
+            auth_api: config.auth_api,
+            iec_api: config.iec_api,
+            engy_api: config.engy_api,
+            ocpp_api: config.ocpp_api,
+            slac_api: config.slac_api,
+            slac_retry: config.slac_retry,
+            ocpp_version: config.ocpp_version,
+            evse_id: config.evse_id,
+            connector_id: config.connector_id,
+            lock: config.lock,
+            relay: config.relay,
+            phase: config.phase,
+            solar: config.solar,
+            tariff: config.tariff,
+            thermal: config.thermal,
+            safety: config.safety,
+            meter: config.meter,
+            ocmf: config.ocmf,
+            iso_vmax: config.iso_vmax,
+            v2g: config.v2g,
+            budget: config.budget,
+            data_transfer: None,
+            contract_validator: None,
             event,
-            data_set: Mutex::new(ChargingState::default()),
-            basic_charging_enabled,
+            data_set: Mutex::new(ChargingState {
+                connector_id: config.connector_id,
+                ..ChargingState::default()
+            }),
+            basic_charging_enabled: config.basic_charging_enabled,
+            fallback: config.fallback,
+            auth_timeout: config.auth_timeout,
+            auth_hold: config.auth_hold,
+            contract_imax: config.contract_imax,
+            contract_pmax: config.contract_pmax,
         };
 
         // return a static handle to prevent Rust from complaining when moving/sharing it
         Box::leak(Box::new(handle))
     }
 
+    pub fn set_data_transfer(
+        &mut self,
+        vendor_id: &'static str,
+        handler: Box<dyn DataTransferHandler>,
+    ) -> &mut Self {
+        self.data_transfer = Some((vendor_id, handler));
+        self
+    }
+
+    pub fn set_contract_validator(&mut self, validator: Box<dyn ContractValidator>) -> &mut Self {
+        self.contract_validator = Some(validator);
+        self
+    }
+
     #[track_caller]
     pub fn get_state(&self) -> Result<MutexGuard<'_, ChargingState>, AfbError> {
         let guard = self.data_set.lock().unwrap();
@@ -73,6 +334,7 @@ impl ManagerHandle {
                 ReservationStatus::Request => {
                     let resa = ReservationState {
                         id: reservation.id,
+                        tagid: reservation.tagid.clone(),
                         start: reservation.start,
                         stop: reservation.stop,
                     };
@@ -121,60 +383,6 @@ impl ManagerHandle {
         Ok(())
     }
 
-    fn auth_rqt(
-        &self,
-        data_set: &mut MutexGuard<ChargingState>,
-        api: AfbApiV4,
-    ) -> Result<(), AfbError> {
-        afb_log_msg!(Notice, self.event, "Requesting idp-login");
-        data_set.auth = AuthMsg::Pending;
-        self.event.push(ChargingMsg::Auth(data_set.auth));
-
-        match AfbSubCall::call_sync(api, self.auth_api, "login", AFB_NO_DATA) {
-            Ok(response) => {
-                let contract = response.get::<&AuthState>(0)?;
-                data_set.auth = contract.auth;
-
-                let response = AfbSubCall::call_sync(
-                    api,
-                    self.engy_api,
-                    "config",
-                    EngyConfSet {
-                        pmax: contract.pmax as i32,
-                        imax: contract.imax as i32,
-                    },
-                )?;
-
-                let engy_conf = response.get::<&EngyConfSet>(0)?;
-                data_set.imax = engy_conf.imax as u32;
-                data_set.pmax = engy_conf.pmax as u32;
-                self.event.push(ChargingMsg::Auth(data_set.auth));
-
-                if matches!(data_set.iso, IsoState::Iec) {
-                    // set imax configuration
-                    AfbSubCall::call_async(
-                        api,
-                        self.iec_api,
-                        "imax",
-                        data_set.imax,
-                        ignore_rsp_cb,
-                        IgnoreRspCtx {},
-                    )?;
-                }
-            }
-            Err(_) => {
-                data_set.auth = AuthMsg::Fail;
-                self.event.push(ChargingMsg::Auth(data_set.auth));
-                AfbSubCall::call_sync(api, self.iec_api, "power", false)?;
-                return afb_error!("charg-iec-auth", "fail idp authentication");
-            }
-        }
-
-        data_set.auth = AuthMsg::Done;
-        afb_log_msg!(Notice, self.event, "Valid idp-auth");
-        Ok(())
-    }
-
     pub fn set_iso_state(&self, msg: &ChargingMsg) -> Result<(), AfbError> {
         let mut data_set = self.get_state()?;
 
@@ -240,67 +448,13 @@ impl ManagerHandle {
         Ok(())
     }
 
-    pub fn slac(&self, api: AfbApiV4, msg: &SlacStatus) -> Result<(), AfbError> {
-        // Inform the firmware that SLAC failed
-        match msg {
-            SlacStatus::TIMEOUT => {
-                AfbSubCall::call_sync(api, self.iec_api, "slac", *msg)?;
-            }
-            _ => {}
-        }
-
-        let mut state = self.get_state()?;
-        let iso_state = match msg {
-            SlacStatus::MATCHED => {
-                /* start ISO15118 Fulup TBD should set imax */
-                IsoState::Iso3
-            }
-            SlacStatus::TIMEOUT => {
-                if self.basic_charging_enabled {
-                    self.auth_rqt(&mut state, api)?; // Warning lock data_set
-                    IsoState::Iec
-                } else {
-                    return Ok(());
-                }
-            }
-
-            _ => {
-                return Ok(()); /* silently ignore any other messages */
-            }
-        };
-        state.iso = iso_state;
-        self.event.push(ChargingMsg::Iso(iso_state));
-
-        if matches!(iso_state, IsoState::Iec) {
-            // Only close the contactor if we are in Basic Charging mode
-            AfbSubCall::call_async(
-                api,
-                self.iec_api,
-                "power",
-                true,
-                ignore_rsp_cb,
-                IgnoreRspCtx {},
-            )?;
-            self.event.push(ChargingMsg::Power(PowerRequest::Start));
-            afb_log_msg!(
-                Notice,
-                self.event,
-                "Slac+Auth done allow power iso_mode:{:?}",
-                iso_state
-            );
-        }
-        Ok(())
-    }
-
-    pub fn ocpp(&self, evt: &AfbEventMsg, msg: &OcppMsg) -> Result<(), AfbError> {
+    pub fn ocpp(&'static self, evt: &AfbEventMsg, msg: &OcppMsg) -> Result<(), AfbError> {
         let mut data_set = self.get_state()?;
         match msg {
             OcppMsg::PowerLimit(limit) => {
                 // in current implementation over-current
                 afb_log_msg!(Warning, evt, "ocpp set power limit:{}", limit.imax);
-                if limit.imax < data_set.imax as i32 && matches!(data_set.iso, IsoState::Iec) {
-                    AfbSubCall::call_sync(evt.get_api(), self.iec_api, "imax", limit.imax)?;
-                }
+                self.limit_profile(&mut data_set, *limit)?;
             }
             OcppMsg::Reservation(reservation) => {
                 // in current implementation over-current
@@ -312,10 +466,9 @@ impl ManagerHandle {
                 );
                 self.reserve(reservation)?;
             }
-            OcppMsg::Reset => {
-                // in current implementation over-current
-                afb_log_msg!(Warning, evt, "ocpp reset power");
-                AfbSubCall::call_sync(evt.get_api(), self.iec_api, "power", false)?;
+            OcppMsg::Reset(reset) => {
+                afb_log_msg!(Warning, evt, "ocpp {:?} reset stop power", reset);
+                self.ocpp_reset(&mut data_set, *reset)?;
             }
 
             OcppMsg::Transaction(status, tid) => {
@@ -327,76 +480,253 @@ impl ManagerHandle {
                     status,
                     tid
                 );
-                let response = AfbSubCall::call_sync(self.apiv4, self.iec_api, "power", *status)?;
-                let data = response.get::<&MeterDataSet>(0)?;
-                AfbSubCall::call_sync(evt.get_api(), self.auth_api, "logout", data.total)?;
-                data_set.power = PowerRequest::Idle;
+                self.ocpp_remote_power(evt, &mut data_set, *status)?;
             }
 
-            _ => {}
+            // start carries CSMS id token, stop the transaction id
+            OcppMsg::RemoteTransaction(true, id_token) => {
+                afb_log_msg!(Notice, evt, "ocpp remote start id_token:{}", id_token);
+                let request = Ocpp201RemoteStart {
+                    id_token: Ocpp201IdToken {
+                        id_token: id_token.clone(),
+                        kind: Ocpp201IdTokenType::Central,
+                    },
+                    remote_start_id: None,
+                };
+                return self.ocpp_remote_start(data_set, &request);
+            }
+
+            OcppMsg::RemoteStart(request) => {
+                afb_log_msg!(
+                    Notice,
+                    evt,
+                    "ocpp remote start id_token:{} remote_start_id:{:?}",
+                    request.id_token.id_token,
+                    request.remote_start_id
+                );
+                return self.ocpp_remote_start(data_set, request);
+            }
+
+            OcppMsg::RemoteTransaction(false, tid) => {
+                if data_set.session.transaction_id.as_deref() == Some(tid.as_str()) {
+                    afb_log_msg!(Notice, evt, "ocpp remote stop tid:{}", tid);
+                    self.ocpp_stop(&mut data_set, OcppStopReason::Remote)?;
+                } else {
+                    afb_log_msg!(Warning, evt, "ocpp remote stop unknown tid:{}", tid);
+                }
+            }
+
+            OcppMsg::Authorized(status) => {
+                afb_log_msg!(Notice, evt, "ocpp authorize verdict:{}", status);
+                self.auth_ocpp_verdict(&mut data_set, *status)?;
+            }
+
+            OcppMsg::Initialized => {
+                afb_log_msg!(Notice, evt, "ocpp backend initialized");
+                self.ocpp_resync(&mut data_set)?;
+            }
+
+            OcppMsg::UnlockConnector => {
+                afb_log_msg!(Warning, evt, "ocpp unlock connector stop session");
+                self.ocpp_stop(&mut data_set, OcppStopReason::UnlockCommand)?;
+            }
+
+            OcppMsg::TriggerMessage(request) => {
+                afb_log_msg!(Notice, evt, "ocpp trigger message:{:?}", request);
+                self.ocpp_trigger(&mut data_set, *request)?;
+            }
+
+            OcppMsg::DataTransfer(request) => {
+                afb_log_msg!(
+                    Notice,
+                    evt,
+                    "ocpp data transfer vendor:{} message:{:?}",
+                    request.vendor_id,
+                    request.message_id
+                );
+                drop(data_set);
+                return self.ocpp_data_transfer(request);
+            }
+
+            OcppMsg::ChangeAvailability(request) => {
+                let status = match self.availability_change(&mut data_set, *request) {
+                    Ok(value) => value,
+                    Err(error) => {
+                        afb_log_msg!(Error, evt, "availability change fail:{}", error.get_info());
+                        OcppAvailabilityStatus::Rejected
+                    }
+                };
+                afb_log_msg!(
+                    Notice,
+                    evt,
+                    "ocpp change availability:{:?} status:{:?}",
+                    request,
+                    status
+                );
+                // CSMS expects Accepted/Rejected/Scheduled in ChangeAvailability response
+                if let Some(ocpp_api) = self.ocpp_api {
+                    AfbSubCall::call_async(
+                        self.apiv4,
+                        ocpp_api,
+                        "availability-done",
+                        status,
+                        ignore_rsp_cb,
+                        IgnoreRspCtx {},
+                    )?;
+                }
+            }
+
+            OcppMsg::Unknown => {
+                afb_log_msg!(Warning, evt, "ocpp unknown message ignored");
+            }
+        }
+        Ok(())
+    }
+
+    fn ocpp_remote_power(
+        &'static self,
+        evt: &AfbEventMsg,
+        data_set: &mut MutexGuard<ChargingState>,
+        status: bool,
+    ) -> Result<(), AfbError> {
+        let response = AfbSubCall::call_sync(self.apiv4, self.iec_api, "power", status)?;
+        let data = response.get::<&MeterDataSet>(0)?;
+        AfbSubCall::call_sync(evt.get_api(), self.auth_api, "logout", data.total)?;
+        data_set.power = PowerRequest::Idle;
+        if !status {
+            self.relay_supervise(data_set, false)?;
+            self.cable_unlock(data_set, false)?;
         }
         Ok(())
     }
 
-    pub fn engy_iover(&self, evt: &AfbEventMsg, msg: &MeterDataSet) -> Result<(), AfbError> {
+    // publish session record and close ocpp transaction started at power allow, reason when aborted by chmgr
+    pub(crate) fn session_close(
+        &'static self,
+        data_set: &mut MutexGuard<ChargingState>,
+        energy: i32,
+        reason: Option<OcppStopReason>,
+    ) -> Result<(), AfbError> {
+        data_set.session.stop = get_timestamp();
+        data_set.session.energy = energy;
+        session_energy(data_set, energy);
+
+        let action = match (data_set.session.transaction, reason) {
+            (false, _) => None,
+            (true, None) => Some(OcppTransaction::Stop(energy)),
+            (true, Some(reason)) => Some(OcppTransaction::Abort(energy, reason)),
+        };
+        // record is published once the signed End reading is known
+        let session = std::mem::take(&mut data_set.session);
+        self.ocmf_close(session, action)?;
+
+        data_set.iso_param = None;
+        data_set.ev = None;
+        data_set.departure = None;
+        data_set.setpoint = 0;
+        solar_reset(data_set);
+        data_set.mode = ChargingMode::Fast;
+        data_set.tariff_hold = false;
+        data_set.limits.contract = None;
+        if !data_set.relay.lockout {
+            data_set.limits.fault = None;
+        }
+        self.limit_update(data_set)
+    }
+
+    // closed session goes to ocpp and listeners, signed End reading already attached
+    pub(crate) fn session_publish(
+        &self,
+        mut session: SessionRecord,
+        action: Option<OcppTransaction>,
+    ) -> Result<(), AfbError> {
+        if let Some(action) = action {
+            self.ocpp_transaction(&mut session, action)?;
+        }
+        self.event.push(ChargingMsg::Session(session));
+        Ok(())
+    }
+
+    pub fn engy_iover(
+        &'static self,
+        evt: &AfbEventMsg,
+        msg: &MeterDataSet,
+    ) -> Result<(), AfbError> {
         let mut data_set = self.get_state()?;
 
         match msg.tag {
             MeterTagSet::OverCurrent => {
                 // in current implementation over-current
                 afb_log_msg!(Warning, evt, "energy over-current stop charge");
-                AfbSubCall::call_sync(evt.get_api(), self.iec_api, "power", false)?;
+                self.relay_open(&mut data_set)?;
                 data_set.power = PowerRequest::Idle;
+                self.v2g_stop(&mut data_set, "over-current")?;
             }
             _ => {}
         }
         Ok(())
     }
 
-    pub fn engy_imax(&self, evt: &AfbEventMsg, imax: u32) -> Result<(), AfbError> {
-        let mut data_set = self.get_state()?;
-
-        if let PowerRequest::Charging(current) = data_set.power {
-            if current > imax {
-                if matches!(data_set.iso, IsoState::Iec) {
-                    AfbSubCall::call_sync(evt.get_api(), self.iec_api, "imax", imax)?;
-                }
-                self.event
-                    .push(ChargingMsg::Power(PowerRequest::Charging(imax)));
-                self.charging_protocol(&mut data_set)?;
+    pub fn engy_imax(&'static self, evt: &AfbEventMsg, imax: u32) -> Result<(), AfbError> {
+        afb_log_msg!(Debug, evt, "energy available imax:{}", imax);
+        // connectors of one chmgr instance share site current like the static budget
+        match self.budget {
+            Some(budget) => budget.site_update(imax)?,
+            None => {
+                let mut data_set = self.get_state()?;
+                self.limit_set(&mut data_set, LimitSource::Site, Some(imax))?;
             }
         }
+
+        let mut data_set = self.get_state()?;
+        // solar modes select phases from PV surplus only, see solar_grid
+        if let SolarMode::Off = data_set.solar {
+            let site = data_set.limits.site.unwrap_or(imax);
+            let available = (site * 3 * PHASE_VOLTAGE) as i32;
+            self.phase_select(&mut data_set, available)?;
+        }
         Ok(())
     }
 
     // added for OCPP RemoteStopTransaction
-    pub fn powerctrl(&self, allow: bool) -> Result<(), AfbError> {
+    pub fn powerctrl(&'static self, allow: bool) -> Result<(), AfbError> {
         let mut data_set = self.get_state()?;
 
+        // cable is locked on authorization, iso power waits for the lock to be verified
+        if allow && self.lock.is_some() && !matches!(data_set.plugged, PlugState::Lock) {
+            return afb_error!("charg-cable-lock", "cable not locked, relay stays open");
+        }
+
         if allow {
             afb_log_msg!(Notice, None, "function remote power triggered, allow power");
             AfbSubCall::call_sync(self.apiv4, self.iec_api, "power", true)?;
+            self.transaction_open(&mut data_set)?;
         } else {
             afb_log_msg!(Notice, None, "function remote power triggered, stop power");
             AfbSubCall::call_sync(self.apiv4, self.iec_api, "power", false)?;
+            self.relay_supervise(&mut data_set, false)?;
             data_set.power = PowerRequest::Idle;
+            self.cable_unlock(&mut data_set, false)?;
         }
 
         Ok(())
     }
 
-    pub fn iec(&self, evt: &AfbEventMsg, msg: &Iec6185Msg) -> Result<(), AfbError> {
+    pub fn iec(&'static self, evt: &AfbEventMsg, msg: &Iec6185Msg) -> Result<(), AfbError> {
         let mut data_set = self.get_state()?;
         match msg {
             Iec6185Msg::PowerRqt(value) => {
                 afb_log_msg!(Notice, self.event, "eic power-request value:{}", value);
-                if *value {
-                    // B => C
-                    data_set.plugged = PlugState::Lock;
-                    self.event.push(ChargingMsg::Plugged(data_set.plugged));
-                } else {
-                    // C => B
-                    data_set.plugged = PlugState::PlugIn;
+                // when chmgr owns the cable lock, plug state follows lock-status register
+                if self.lock.is_none() {
+                    if *value {
+                        // B => C
+                        data_set.plugged = PlugState::Lock;
+                        self.event.push(ChargingMsg::Plugged(data_set.plugged));
+                    } else {
+                        // C => B
+                        data_set.plugged = PlugState::PlugIn;
+                    }
                 }
             }
             Iec6185Msg::CableImax(value) => {
@@ -407,31 +737,25 @@ impl ManagerHandle {
                     value,
                     data_set.imax
                 );
-                data_set.imax = *value;
+                self.limit_set(&mut data_set, LimitSource::Cable, Some(*value))?;
             }
-            Iec6185Msg::Error(_value) => {
-                data_set.imax = 0;
+            Iec6185Msg::Error(value) => {
+                self.limit_set(&mut data_set, LimitSource::Fault, Some(0))?;
+                self.v2g_stop(&mut data_set, value)?;
             }
             Iec6185Msg::RelayOn(value) => {
+                meter_settle(&mut data_set);
                 if *value {
                     // vehicle start charging
+                    // iec imax was already written by power_close or the limit arbiter
                     data_set.power = PowerRequest::Charging(data_set.imax);
-                    if matches!(data_set.iso, IsoState::Iec) {
-                        AfbSubCall::call_sync(
-                            evt.get_apiv4(),
-                            self.iec_api,
-                            "imax",
-                            data_set.imax,
-                        )?;
-                    }
-                    if self.ocpp_api.is_some() {
-                        AfbSubCall::call_sync(
-                            evt.get_apiv4(),
-                            self.ocpp_api.unwrap(),
-                            "status-notification",
-                            OcppChargerStatus::Charging,
-                        )?;
-                    }
+                    // engy was silent while the relay was open, stale check restarts now
+                    data_set.meter.updated = Some(get_timestamp());
+                    self.ocpp_notify(OcppChargerStatus::Charging)?;
+                    self.ocpp_transaction_update(
+                        &mut data_set,
+                        Ocpp201TriggerReason::ChargingStateChanged,
+                    )?;
                 } else {
                     // vehicle stop charging
                     let response = AfbSubCall::call_sync(
@@ -441,10 +765,12 @@ impl ManagerHandle {
                         EnergyAction::READ,
                     )?;
                     let data = response.get::<&MeterDataSet>(0)?;
+                    self.meter_energy(&mut data_set, data.total)?;
+                    // relay opening is not an unplug, session may resume (phase switch, pause, ...)
                     data_set.power = PowerRequest::Stop(data.total);
-                    data_set.plugged = PlugState::PlugOut;
                 }
                 self.event.push(ChargingMsg::Power(data_set.power));
+                self.relay_supervise(&mut data_set, *value)?;
                 // Avoid charging type message in cp status C => B
                 match data_set.power {
                     PowerRequest::Charging(_) => {
@@ -464,14 +790,11 @@ impl ManagerHandle {
                 let data = response.get::<&MeterDataSet>(0)?;
 
                 let plug_state = if *value {
-                    if self.ocpp_api.is_some() {
-                        AfbSubCall::call_sync(
-                            evt.get_apiv4(),
-                            self.ocpp_api.unwrap(),
-                            "status-notification",
-                            OcppChargerStatus::Reserved,
-                        )?;
+                    if let AvailabilityState::Inoperative = data_set.availability {
+                        afb_log_msg!(Warning, self.event, "Charger inoperative, plug-in refused");
+                        return Ok(());
                     }
+                    self.ocpp_notify(OcppChargerStatus::Reserved)?;
                     match data_set.plugged {
                         PlugState::PlugIn => {
                             return Ok(());
@@ -480,6 +803,17 @@ impl ManagerHandle {
                             data_set.plugged = PlugState::PlugIn;
                         }
                     }
+                    data_set.session.start = get_timestamp();
+                    meter_reset(&mut data_set);
+                    if let Some(true) = self.safety.as_ref().map(|config| config.on_plugin) {
+                        self.safety_start(&mut data_set)?;
+                    }
+                    // tap-then-plug: authorization obtained before plug-in is consumed by this session
+                    if let AuthMsg::Done = data_set.auth {
+                        afb_log_msg!(Notice, self.event, "Consume idp-auth held before plug-in");
+                        self.event.push(ChargingMsg::Auth(data_set.auth));
+                        self.auth_allow_power(&mut data_set)?;
+                    }
                     PlugState::PlugIn
                 } else {
                     afb_log_msg!(
@@ -489,6 +823,17 @@ impl ManagerHandle {
                         self.auth_api,
                         data.total
                     );
+                    // close any running SLAC attempt before publishing session diagnostics
+                    self.slac_reset(&mut data_set);
+                    self.meter_energy(&mut data_set, data.total)?;
+                    self.session_close(&mut data_set, data.total, None)?;
+                    meter_reset(&mut data_set);
+                    // session is over, also drop any idp-login still waiting for a tap
+                    self.auth_reset(&mut data_set);
+                    self.cable_unlock(&mut data_set, false)?;
+                    if let AvailabilityState::Scheduled = data_set.availability {
+                        self.availability_apply(&mut data_set, AvailabilityState::Inoperative)?;
+                    }
                     let power = {
                         data_set.plugged = PlugState::PlugOut;
                         data_set.power = PowerRequest::Idle;
@@ -506,6 +851,10 @@ impl ManagerHandle {
                 self.event.push(ChargingMsg::Plugged(plug_state));
             }
         }
-        Ok(())
+
+        // shared budget updates every connector, release our state first
+        let active = matches!(data_set.power, PowerRequest::Charging(_));
+        drop(data_set);
+        self.budget_update(active)
     }
 }
@@ -0,0 +1,132 @@
+/*
+ * Copyright (C) 2015-2022 IoT.bzh Company
+ * Author: Fulup Ar Foll <fulup@iot.bzh>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *   http://www.apache.org/licenses/LICENSE-2.0
+ *
+ */
+
+use crate::ocpp::*;
+use crate::prelude::*;
+use afbv4::prelude::*;
+use std::sync::MutexGuard;
+use std::time::Instant;
+use typesv4::prelude::*;
+
+// EV has 5s to follow a new pwm duty cycle or relay switch
+const METER_SETTLE_MS: u128 = 5000;
+
+// new session or connector free, meter register restarts from reset value
+pub(crate) fn meter_reset(data_set: &mut ChargingState) {
+    fault_clear(data_set, OcppErrorCode::PowerMeterFailure);
+    data_set.meter = MeterDiag {
+        updated: Some(get_timestamp()),
+        ..MeterDiag::default()
+    };
+}
+
+// relay or imax changed, current check waits for the EV to follow
+pub(crate) fn meter_settle(data_set: &mut ChargingState) {
+    data_set.meter.settle = Some(Instant::now());
+}
+
+impl ManagerHandle {
+    // energy register only goes backward while discharging
+    pub(crate) fn meter_energy(
+        &self,
+        data_set: &mut MutexGuard<ChargingState>,
+        total: i32,
+    ) -> Result<(), AfbError> {
+        if self.meter.is_none() {
+            return Ok(());
+        }
+        data_set.meter.updated = Some(get_timestamp());
+        if let Some(last) = data_set.meter.energy {
+            if total < last && data_set.setpoint >= 0 {
+                let info = format!("energy register decreased {}->{}", last, total);
+                self.meter_fault(data_set, &info)?;
+            }
+        }
+        data_set.meter.energy = Some(total);
+        Ok(())
+    }
+
+    // phase currents (mA) from energy manager
+    pub fn engy_current(&self, msg: &MeterDataSet) -> Result<(), AfbError> {
+        let config = match &self.meter {
+            Some(value) => value,
+            None => return Ok(()),
+        };
+        let mut data_set = self.get_state()?;
+        let current = msg.l1.max(msg.l2).max(msg.l3);
+        data_set.meter.updated = Some(get_timestamp());
+        data_set.meter.current = Some(current);
+
+        if let Some(settle) = data_set.meter.settle {
+            if settle.elapsed().as_millis() < METER_SETTLE_MS {
+                return Ok(());
+            }
+        }
+        match data_set.power {
+            PowerRequest::Charging(imax) => {
+                let limit = ((imax + config.tolerance) * 1000) as i32;
+                if current > limit {
+                    let info = format!("current:{}mA above imax:{}A", current, imax);
+                    self.meter_fault(&mut data_set, &info)?;
+                }
+            }
+            _ => {
+                if current > config.idle {
+                    let info = format!("current:{}mA with relay open", current);
+                    self.meter_fault(&mut data_set, &info)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // periodic check from meter timer, readings must keep coming while charging
+    pub fn meter_tic(&self) -> Result<(), AfbError> {
+        let config = match &self.meter {
+            Some(value) => value,
+            None => return Ok(()),
+        };
+        let mut data_set = self.get_state()?;
+        // engy stops publishing while the relay is open (tariff or solar hold, pause)
+        if !matches!(data_set.power, PowerRequest::Charging(_)) {
+            return Ok(());
+        }
+        let elapsed = match data_set.meter.updated {
+            Some(updated) => get_timestamp().saturating_sub(updated).as_millis(),
+            None => return Ok(()),
+        };
+        if elapsed > config.stale as u128 {
+            let info = format!("no meter update since {}ms", elapsed);
+            self.meter_fault(&mut data_set, &info)?;
+        }
+        Ok(())
+    }
+
+    // session stays billable only with plausible readings
+    fn meter_fault(
+        &self,
+        data_set: &mut MutexGuard<ChargingState>,
+        info: &str,
+    ) -> Result<(), AfbError> {
+        if data_set.meter.failure.is_some() {
+            return Ok(());
+        }
+        afb_log_msg!(Error, self.event, "meter implausible {}", info);
+        data_set.meter.failure = Some(info.to_string());
+        if is_plugged(data_set) {
+            data_set.session.unreliable = true;
+        }
+        fault_set(data_set, OcppErrorCode::PowerMeterFailure);
+        self.event.push(ChargingMsg::Meter(data_set.meter.clone()));
+        self.ocpp_notify(ocpp_status(data_set))
+    }
+}
@@ -0,0 +1,126 @@
+/*
+ * Copyright (C) 2015-2022 IoT.bzh Company
+ * Author: Fulup Ar Foll <fulup@iot.bzh>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *   http://www.apache.org/licenses/LICENSE-2.0
+ *
+ */
+
+use crate::limit::*;
+use crate::phase::*;
+use crate::prelude::*;
+use afbv4::prelude::*;
+use std::sync::MutexGuard;
+use typesv4::prelude::*;
+
+// local time in minutes of the day
+fn tariff_minute(config: &TariffConfig) -> u32 {
+    let now = get_timestamp().as_secs() as i64 / 60 + config.utc_offset as i64;
+    now.rem_euclid(24 * 60) as u32
+}
+
+// off-peak window, both bounds in minutes of the day (window may wrap over midnight)
+fn tariff_offpeak(config: &TariffConfig) -> bool {
+    let minute = tariff_minute(config);
+    if config.offpeak_start <= config.offpeak_stop {
+        minute >= config.offpeak_start && minute < config.offpeak_stop
+    } else {
+        minute >= config.offpeak_start || minute < config.offpeak_stop
+    }
+}
+
+// EV energy request cannot be delivered before departure when waiting for off-peak
+fn tariff_urgent(config: &TariffConfig, data_set: &ChargingState) -> bool {
+    let energy = data_set.ev.as_ref().and_then(|ev| ev.energy_request);
+    let (departure, energy) = match (data_set.departure, energy) {
+        (Some(departure), Some(energy)) => (departure.as_secs(), energy as u64),
+        _ => return false,
+    };
+
+    // charge current once released, tariff hold itself is not a constraint
+    let limits = ImaxLimits {
+        mode: None,
+        ..data_set.limits.clone()
+    };
+    let (imax, _) = limit_min(&limits, true);
+    let power = (imax * data_set.phases * PHASE_VOLTAGE) as u64;
+    if power == 0 {
+        return false;
+    }
+
+    let wait = (config.offpeak_start as i64 - tariff_minute(config) as i64).rem_euclid(24 * 60);
+    let charge = energy * 3600 / power;
+    get_timestamp().as_secs() + wait as u64 * 60 + charge > departure
+}
+
+impl ManagerHandle {
+    // ocpp profile and energy manager still cap every mode, mode only selects the local source
+    pub fn charging_mode(&'static self, mode: ChargingMode) -> Result<(), AfbError> {
+        let mut data_set = self.get_state()?;
+        match mode {
+            ChargingMode::Eco | ChargingMode::Solar if self.solar.is_none() => {
+                return afb_error!("chmgr-charging-mode", "no grid power source configured")
+            }
+            ChargingMode::Scheduled if self.tariff.is_none() => {
+                return afb_error!("chmgr-charging-mode", "no tariff schedule configured")
+            }
+            _ => {}
+        }
+
+        afb_log_msg!(Notice, self.event, "charging mode:{:?}", mode);
+        data_set.mode = mode;
+        self.event.push(ChargingMsg::Mode(mode));
+
+        let solar = match mode {
+            ChargingMode::Eco => SolarMode::Hybrid,
+            ChargingMode::Solar => SolarMode::Solar,
+            ChargingMode::Fast | ChargingMode::Scheduled => SolarMode::Off,
+        };
+        self.solar_apply(&mut data_set, solar)?;
+        self.tariff_check(&mut data_set)
+    }
+
+    // periodic check from tariff timer
+    pub fn tariff_tic(&'static self) -> Result<(), AfbError> {
+        let mut data_set = self.get_state()?;
+        self.tariff_check(&mut data_set)
+    }
+
+    pub(crate) fn tariff_check(
+        &'static self,
+        data_set: &mut MutexGuard<ChargingState>,
+    ) -> Result<(), AfbError> {
+        let hold = match (&self.tariff, data_set.mode) {
+            (Some(config), ChargingMode::Scheduled) => {
+                !tariff_offpeak(config) && !tariff_urgent(config, data_set)
+            }
+            _ => false,
+        };
+        if hold == data_set.tariff_hold {
+            return Ok(());
+        }
+
+        data_set.tariff_hold = hold;
+        self.limit_update(data_set)?;
+        if hold {
+            afb_log_msg!(Notice, self.event, "peak tariff, hold charge");
+            if let PowerRequest::Charging(_) = data_set.power {
+                self.relay_open(data_set)?;
+            }
+        } else {
+            afb_log_msg!(
+                Notice,
+                self.event,
+                "off-peak tariff or EV departure, resume charge"
+            );
+            if session_active(data_set) {
+                self.power_close(data_set)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,162 @@
+/*
+ * Copyright (C) 2015-2022 IoT.bzh Company
+ * Author: Fulup Ar Foll <fulup@iot.bzh>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *   http://www.apache.org/licenses/LICENSE-2.0
+ *
+ */
+
+use crate::prelude::*;
+use afbv4::prelude::*;
+use std::sync::MutexGuard;
+use std::time::Duration;
+use typesv4::prelude::*;
+
+// Begin belongs to the running session, End carries the closed session record
+enum OcmfStep {
+    Begin(Duration),
+    End(SessionRecord, Option<OcppTransaction>),
+}
+
+struct OcmfCtx {
+    mgr: &'static ManagerHandle,
+    step: OcmfStep,
+}
+
+fn ocmf_snapshot_cb(_api: &AfbApi, args: &AfbRqtData, ctx: &AfbCtxData) -> Result<(), AfbError> {
+    let ctx = ctx.get_ref::<OcmfCtx>()?;
+
+    let snapshot = match args.get::<&OcmfSnapshot>(0) {
+        Ok(value) => Some(value.clone()),
+        Err(error) => {
+            afb_log_msg!(Error, None, "ocmf snapshot fail:{}", error.get_info());
+            None
+        }
+    };
+    match &ctx.step {
+        OcmfStep::Begin(start) => ctx.mgr.ocmf_begin(*start, snapshot),
+        OcmfStep::End(session, action) => {
+            ctx.mgr.ocmf_end(session.clone(), action.clone(), snapshot)
+        }
+    }
+}
+
+fn ocmf_taken(session: &SessionRecord, context: OcmfContext) -> bool {
+    session
+        .ocmf
+        .iter()
+        .any(|snapshot| snapshot.context == context)
+}
+
+fn ocmf_request(session: &SessionRecord, context: OcmfContext) -> OcmfRequest {
+    OcmfRequest {
+        context,
+        tagid: session.tagid.clone(),
+        transaction_id: session.transaction_id.clone(),
+    }
+}
+
+// a missing snapshot makes the session non billing grade
+fn ocmf_store(session: &mut SessionRecord, snapshot: Option<OcmfSnapshot>) -> bool {
+    match snapshot {
+        Some(snapshot) => {
+            session.ocmf.push(snapshot);
+            true
+        }
+        None => {
+            session.unreliable = true;
+            false
+        }
+    }
+}
+
+impl ManagerHandle {
+    // signed reading from meter, answer is handled by ocmf_begin/ocmf_end
+    fn ocmf_snapshot(
+        &'static self,
+        verb: &'static str,
+        request: OcmfRequest,
+        step: OcmfStep,
+    ) -> Result<(), AfbError> {
+        AfbSubCall::call_async(
+            self.apiv4,
+            self.engy_api,
+            verb,
+            request,
+            ocmf_snapshot_cb,
+            OcmfCtx { mgr: self, step },
+        )?;
+        Ok(())
+    }
+
+    // first relay close of the session, after ocpp transaction start so its id is known
+    pub(crate) fn ocmf_open(
+        &'static self,
+        data_set: &mut MutexGuard<ChargingState>,
+    ) -> Result<(), AfbError> {
+        let verb = match self.ocmf {
+            Some(value) => value,
+            None => return Ok(()),
+        };
+        if ocmf_taken(&data_set.session, OcmfContext::Begin) {
+            return Ok(());
+        }
+        let request = ocmf_request(&data_set.session, OcmfContext::Begin);
+        self.ocmf_snapshot(verb, request, OcmfStep::Begin(data_set.session.start))
+    }
+
+    fn ocmf_begin(&self, start: Duration, snapshot: Option<OcmfSnapshot>) -> Result<(), AfbError> {
+        let mut data_set = self.get_state()?;
+        // session closed or Begin already received while waiting for the meter
+        if data_set.session.start != start || ocmf_taken(&data_set.session, OcmfContext::Begin) {
+            return Ok(());
+        }
+        if let Some(snapshot) = &snapshot {
+            afb_log_msg!(
+                Notice,
+                self.event,
+                "ocmf Begin reading:{}",
+                snapshot.reading
+            );
+        }
+        if ocmf_store(&mut data_set.session, snapshot) {
+            self.ocpp_signed_begin(&mut data_set.session)?;
+        }
+        Ok(())
+    }
+
+    // session end, record and ocpp transaction stop wait for the End reading
+    pub(crate) fn ocmf_close(
+        &'static self,
+        session: SessionRecord,
+        action: Option<OcppTransaction>,
+    ) -> Result<(), AfbError> {
+        match self.ocmf {
+            Some(verb)
+                if ocmf_taken(&session, OcmfContext::Begin)
+                    && !ocmf_taken(&session, OcmfContext::End) =>
+            {
+                let request = ocmf_request(&session, OcmfContext::End);
+                self.ocmf_snapshot(verb, request, OcmfStep::End(session, action))
+            }
+            _ => self.session_publish(session, action),
+        }
+    }
+
+    fn ocmf_end(
+        &self,
+        mut session: SessionRecord,
+        action: Option<OcppTransaction>,
+        snapshot: Option<OcmfSnapshot>,
+    ) -> Result<(), AfbError> {
+        if let Some(snapshot) = &snapshot {
+            afb_log_msg!(Notice, self.event, "ocmf End reading:{}", snapshot.reading);
+        }
+        ocmf_store(&mut session, snapshot);
+        self.session_publish(session, action)
+    }
+}
@@ -0,0 +1,684 @@
+/*
+ * Copyright (C) 2015-2022 IoT.bzh Company
+ * Author: Fulup Ar Foll <fulup@iot.bzh>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *   http://www.apache.org/licenses/LICENSE-2.0
+ *
+ */
+
+use crate::prelude::*;
+use afbv4::prelude::*;
+use std::sync::MutexGuard;
+use typesv4::prelude::*;
+
+struct StopCtx {
+    mgr: &'static ManagerHandle,
+    reason: OcppStopReason,
+}
+
+fn ocpp_stop_cb(_api: &AfbApi, args: &AfbRqtData, ctx: &AfbCtxData) -> Result<(), AfbError> {
+    let ctx = ctx.get_ref::<StopCtx>()?;
+
+    let energy = match args.get::<&MeterDataSet>(0) {
+        Ok(meter) => meter.total,
+        Err(error) => {
+            afb_log_msg!(
+                Error,
+                None,
+                "ocpp stop meter read fail:{}",
+                error.get_info()
+            );
+            0
+        }
+    };
+    ctx.mgr.ocpp_stop_done(ctx.reason, energy)
+}
+
+struct MeterValuesCtx {
+    mgr: &'static ManagerHandle,
+}
+
+fn ocpp_meter_values_cb(
+    _api: &AfbApi,
+    args: &AfbRqtData,
+    ctx: &AfbCtxData,
+) -> Result<(), AfbError> {
+    let ctx = ctx.get_ref::<MeterValuesCtx>()?;
+    let meter = args.get::<&MeterDataSet>(0)?;
+    ctx.mgr.ocpp_meter_values(meter)
+}
+
+// signed reading taken for this transaction step, see ocmf_open/ocmf_close
+fn ocpp_signed_meter(
+    session: &SessionRecord,
+    connector_id: u32,
+    context: OcmfContext,
+) -> Option<OcppSignedMeter> {
+    let reading_context = match context {
+        OcmfContext::Begin => OcppReadingContext::TransactionBegin,
+        OcmfContext::End => OcppReadingContext::TransactionEnd,
+    };
+    session
+        .ocmf
+        .iter()
+        .find(|snapshot| snapshot.context == context)
+        .map(|snapshot| OcppSignedMeter {
+            context: reading_context,
+            connector_id,
+            value: snapshot.reading,
+            signed_data: snapshot.payload.clone(),
+            encoding: "OCMF".to_string(),
+            public_key: snapshot.public_key.clone(),
+        })
+}
+
+// connector status as seen by the CSMS, computed from current charging state
+pub(crate) fn ocpp_status(data_set: &ChargingState) -> OcppChargerStatus {
+    if let Some(fault) = &data_set.fault {
+        return OcppChargerStatus::Error(fault.clone());
+    }
+    if let AvailabilityState::Inoperative = data_set.availability {
+        return OcppChargerStatus::Unavailable;
+    }
+    match data_set.plugged {
+        PlugState::Error => OcppChargerStatus::Error(OcppErrorCode::OtherError),
+        PlugState::PlugIn | PlugState::Lock => match data_set.power {
+            PowerRequest::Charging(_) => OcppChargerStatus::Charging,
+            PowerRequest::Stop(_) => OcppChargerStatus::Finishing,
+            _ => OcppChargerStatus::Preparing,
+        },
+        PlugState::PlugOut | PlugState::Unknown => match data_set.reservation {
+            Some(_) => OcppChargerStatus::Reserved,
+            None => OcppChargerStatus::Available,
+        },
+    }
+}
+
+fn ocpp201_status(status: &OcppChargerStatus) -> Ocpp201ConnectorStatus {
+    match status {
+        OcppChargerStatus::Available => Ocpp201ConnectorStatus::Available,
+        OcppChargerStatus::Reserved => Ocpp201ConnectorStatus::Reserved,
+        OcppChargerStatus::Unavailable => Ocpp201ConnectorStatus::Unavailable,
+        OcppChargerStatus::Error(_) => Ocpp201ConnectorStatus::Faulted,
+        OcppChargerStatus::Preparing
+        | OcppChargerStatus::Charging
+        | OcppChargerStatus::Finishing => Ocpp201ConnectorStatus::Occupied,
+    }
+}
+
+fn ocpp201_trigger(reason: OcppStopReason) -> Ocpp201TriggerReason {
+    match reason {
+        OcppStopReason::DeAuthorized => Ocpp201TriggerReason::Deauthorized,
+        OcppStopReason::EVDisconnected => Ocpp201TriggerReason::EVDeparted,
+        OcppStopReason::HardReset | OcppStopReason::SoftReset => Ocpp201TriggerReason::ResetCommand,
+        OcppStopReason::Local => Ocpp201TriggerReason::StopAuthorized,
+        OcppStopReason::Remote => Ocpp201TriggerReason::RemoteStop,
+        OcppStopReason::UnlockCommand => Ocpp201TriggerReason::UnlockCommand,
+        _ => Ocpp201TriggerReason::AbnormalCondition,
+    }
+}
+
+impl ManagerHandle {
+    // every ocpp message goes through following helpers, charging logic ignores ocpp flavour
+    pub(crate) fn ocpp_notify(&self, status: OcppChargerStatus) -> Result<(), AfbError> {
+        match self.ocpp_version {
+            OcppVersion::V16 => {
+                if let Some(ocpp_api) = self.ocpp_api {
+                    AfbSubCall::call_async(
+                        self.apiv4,
+                        ocpp_api,
+                        "status-notification",
+                        Ocpp16StatusNotification {
+                            connector_id: self.connector_id,
+                            status,
+                        },
+                        ignore_rsp_cb,
+                        IgnoreRspCtx {},
+                    )?;
+                }
+            }
+            OcppVersion::V201 => {
+                let notification = Ocpp201StatusNotification {
+                    evse_id: self.evse_id,
+                    connector_id: self.connector_id,
+                    status: ocpp201_status(&status),
+                };
+                if let Some(ocpp_api) = self.ocpp_api {
+                    AfbSubCall::call_async(
+                        self.apiv4,
+                        ocpp_api,
+                        "status-notification",
+                        notification,
+                        ignore_rsp_cb,
+                        IgnoreRspCtx {},
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn ocpp_authorize(&self, tagid: &str) -> Result<(), AfbError> {
+        match self.ocpp_version {
+            OcppVersion::V16 => {
+                if let Some(ocpp_api) = self.ocpp_api {
+                    AfbSubCall::call_async(
+                        self.apiv4,
+                        ocpp_api,
+                        "authorize",
+                        Ocpp16Authorize {
+                            connector_id: self.connector_id,
+                            tagid: tagid.to_string(),
+                        },
+                        ignore_rsp_cb,
+                        IgnoreRspCtx {},
+                    )?;
+                }
+            }
+            OcppVersion::V201 => {
+                let id_token = Ocpp201IdToken {
+                    id_token: tagid.to_string(),
+                    kind: Ocpp201IdTokenType::ISO14443,
+                };
+                if let Some(ocpp_api) = self.ocpp_api {
+                    AfbSubCall::call_async(
+                        self.apiv4,
+                        ocpp_api,
+                        "authorize",
+                        id_token,
+                        ignore_rsp_cb,
+                        IgnoreRspCtx {},
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // ocpp binding wraps it in a 1.6 DataTransfer or sends a 2.0.1 eMAID Authorize
+    pub(crate) fn ocpp_authorize_pnc(&self, contract: &PncContract) -> Result<(), AfbError> {
+        // without OCSP hash data, CSMS validates the leaf certificate itself
+        let certificate = if contract.hash_data.is_empty() {
+            Some(contract.certificate.clone())
+        } else {
+            None
+        };
+        if let Some(ocpp_api) = self.ocpp_api {
+            AfbSubCall::call_async(
+                self.apiv4,
+                ocpp_api,
+                "authorize-pnc",
+                OcppPncAuthorize {
+                    version: self.ocpp_version,
+                    connector_id: self.connector_id,
+                    emaid: contract.emaid.clone(),
+                    certificate,
+                    hash_data: contract.hash_data.clone(),
+                },
+                ignore_rsp_cb,
+                IgnoreRspCtx {},
+            )?;
+        }
+        Ok(())
+    }
+
+    // 1.6 Start/StopTransaction, 2.0.1 TransactionEvent Started/Ended
+    pub(crate) fn ocpp_transaction(
+        &self,
+        session: &mut SessionRecord,
+        action: OcppTransaction,
+    ) -> Result<(), AfbError> {
+        if let OcppVersion::V16 = self.ocpp_version {
+            if let Some(ocpp_api) = self.ocpp_api {
+                // End must reach ocpp before StopTransaction, Begin follows from ocmf_open
+                if let (OcppTransaction::Stop(_) | OcppTransaction::Abort(_, _), Some(signed)) = (
+                    &action,
+                    ocpp_signed_meter(session, self.connector_id, OcmfContext::End),
+                ) {
+                    self.ocpp_signed_values(ocpp_api, signed)?;
+                }
+                AfbSubCall::call_async(
+                    self.apiv4,
+                    ocpp_api,
+                    "transaction",
+                    Ocpp16Transaction {
+                        connector_id: self.connector_id,
+                        action,
+                    },
+                    ignore_rsp_cb,
+                    IgnoreRspCtx {},
+                )?;
+            }
+            return Ok(());
+        }
+
+        let (event_type, trigger_reason, id_token, meter, stopped_reason) = match action {
+            OcppTransaction::Start(tagid) => {
+                session.transaction_id = Some(format!(
+                    "{}-{}-{}",
+                    self.evse_id,
+                    self.connector_id,
+                    session.start.as_secs()
+                ));
+                session.seq_no = 0;
+                let (trigger_reason, kind) = match &session.remote_start {
+                    Some(remote) => (Ocpp201TriggerReason::RemoteStart, remote.id_token.kind),
+                    None if session.pnc => {
+                        (Ocpp201TriggerReason::Authorized, Ocpp201IdTokenType::EMAID)
+                    }
+                    None => (
+                        Ocpp201TriggerReason::Authorized,
+                        Ocpp201IdTokenType::ISO14443,
+                    ),
+                };
+                let id_token = Ocpp201IdToken {
+                    id_token: tagid,
+                    kind,
+                };
+                (
+                    Ocpp201TransactionEventType::Started,
+                    trigger_reason,
+                    Some(id_token),
+                    None,
+                    None,
+                )
+            }
+            OcppTransaction::Stop(energy) => (
+                Ocpp201TransactionEventType::Ended,
+                Ocpp201TriggerReason::EVDeparted,
+                None,
+                Some(energy),
+                Some(OcppStopReason::EVDisconnected),
+            ),
+            OcppTransaction::Abort(energy, reason) => (
+                Ocpp201TransactionEventType::Ended,
+                ocpp201_trigger(reason),
+                None,
+                Some(energy),
+                Some(reason),
+            ),
+        };
+        self.ocpp201_event(
+            session,
+            event_type,
+            trigger_reason,
+            id_token,
+            meter,
+            stopped_reason,
+        )
+    }
+
+    // signed Begin reading arrives after transaction start, sent on its own
+    pub(crate) fn ocpp_signed_begin(&self, session: &mut SessionRecord) -> Result<(), AfbError> {
+        let (ocpp_api, signed) = match (
+            self.ocpp_api,
+            ocpp_signed_meter(session, self.connector_id, OcmfContext::Begin),
+        ) {
+            (Some(ocpp_api), Some(signed)) if session.transaction => (ocpp_api, signed),
+            _ => return Ok(()),
+        };
+        match self.ocpp_version {
+            OcppVersion::V16 => self.ocpp_signed_values(ocpp_api, signed),
+            OcppVersion::V201 => self.ocpp201_event(
+                session,
+                Ocpp201TransactionEventType::Updated,
+                Ocpp201TriggerReason::SignedDataReceived,
+                None,
+                None,
+                None,
+            ),
+        }
+    }
+
+    fn ocpp_signed_values(
+        &self,
+        ocpp_api: &'static str,
+        signed: OcppSignedMeter,
+    ) -> Result<(), AfbError> {
+        AfbSubCall::call_async(
+            self.apiv4,
+            ocpp_api,
+            "signed-meter-values",
+            signed,
+            ignore_rsp_cb,
+            IgnoreRspCtx {},
+        )?;
+        Ok(())
+    }
+
+    // 2.0.1 only, 1.6 reports charging state changes through status-notification
+    pub(crate) fn ocpp_transaction_update(
+        &self,
+        data_set: &mut MutexGuard<ChargingState>,
+        trigger_reason: Ocpp201TriggerReason,
+    ) -> Result<(), AfbError> {
+        match (self.ocpp_version, data_set.session.transaction) {
+            (OcppVersion::V201, true) => self.ocpp201_event(
+                &mut data_set.session,
+                Ocpp201TransactionEventType::Updated,
+                trigger_reason,
+                None,
+                None,
+                None,
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    fn ocpp201_event(
+        &self,
+        session: &mut SessionRecord,
+        event_type: Ocpp201TransactionEventType,
+        trigger_reason: Ocpp201TriggerReason,
+        id_token: Option<Ocpp201IdToken>,
+        meter: Option<i32>,
+        stopped_reason: Option<OcppStopReason>,
+    ) -> Result<(), AfbError> {
+        let transaction_id = match &session.transaction_id {
+            Some(value) => value.clone(),
+            None => return afb_error!("chmgr-ocpp-transaction", "no ocpp 2.0.1 transaction id"),
+        };
+        let event = Ocpp201TransactionEvent {
+            event_type,
+            trigger_reason,
+            seq_no: session.seq_no,
+            transaction_id,
+            evse_id: self.evse_id,
+            connector_id: self.connector_id,
+            id_token,
+            stopped_reason,
+            meter,
+            signed_meter: match (event_type, trigger_reason) {
+                (
+                    Ocpp201TransactionEventType::Updated,
+                    Ocpp201TriggerReason::SignedDataReceived,
+                ) => ocpp_signed_meter(session, self.connector_id, OcmfContext::Begin),
+                (Ocpp201TransactionEventType::Ended, _) => {
+                    ocpp_signed_meter(session, self.connector_id, OcmfContext::End)
+                }
+                _ => None,
+            },
+            remote_start_id: match event_type {
+                Ocpp201TransactionEventType::Started => session
+                    .remote_start
+                    .as_ref()
+                    .and_then(|remote| remote.remote_start_id),
+                _ => None,
+            },
+        };
+        session.seq_no += 1;
+        if let Some(ocpp_api) = self.ocpp_api {
+            AfbSubCall::call_async(
+                self.apiv4,
+                ocpp_api,
+                "transaction-event",
+                event,
+                ignore_rsp_cb,
+                IgnoreRspCtx {},
+            )?;
+        }
+        Ok(())
+    }
+
+    // ocpp backend (re)connected, push everything the CSMS needs to rebuild its view
+    pub(crate) fn ocpp_resync(
+        &'static self,
+        data_set: &mut MutexGuard<ChargingState>,
+    ) -> Result<(), AfbError> {
+        let status = ocpp_status(data_set);
+        afb_log_msg!(Notice, self.event, "ocpp resync status:{:?}", status);
+        self.ocpp_notify(status)?;
+
+        // transaction opened before reconnect is still known by CSMS, only its meter is refreshed
+        if !data_set.session.transaction {
+            return Ok(());
+        }
+        match self.ocpp_version {
+            OcppVersion::V16 => self.ocpp_meter_read(),
+            OcppVersion::V201 => {
+                self.ocpp_transaction_update(data_set, Ocpp201TriggerReason::Trigger)
+            }
+        }
+    }
+
+    pub(crate) fn ocpp_reset(
+        &'static self,
+        data_set: &mut MutexGuard<ChargingState>,
+        reset: OcppResetType,
+    ) -> Result<(), AfbError> {
+        let reason = match reset {
+            OcppResetType::Soft => OcppStopReason::SoftReset,
+            OcppResetType::Hard => OcppStopReason::HardReset,
+        };
+        self.ocpp_stop(data_set, reason)
+    }
+
+    // stop power and wait for final meter reading before closing transaction
+    pub(crate) fn ocpp_stop(
+        &'static self,
+        data_set: &mut MutexGuard<ChargingState>,
+        reason: OcppStopReason,
+    ) -> Result<(), AfbError> {
+        self.relay_open(data_set)?;
+        data_set.power = PowerRequest::Idle;
+        self.event.push(ChargingMsg::Power(data_set.power));
+
+        AfbSubCall::call_async(
+            self.apiv4,
+            self.engy_api,
+            "energy",
+            EnergyAction::READ,
+            ocpp_stop_cb,
+            StopCtx { mgr: self, reason },
+        )?;
+        Ok(())
+    }
+
+    fn ocpp_stop_done(&'static self, reason: OcppStopReason, energy: i32) -> Result<(), AfbError> {
+        let mut data_set = self.get_state()?;
+
+        // reset or unlock while idle has no session to publish nor user to log out
+        if data_set.session.tagid.is_some() || !data_set.session.start.is_zero() {
+            self.session_close(&mut data_set, energy, Some(reason))?;
+            AfbSubCall::call_async(
+                self.apiv4,
+                self.auth_api,
+                "logout",
+                energy,
+                ignore_rsp_cb,
+                IgnoreRspCtx {},
+            )?;
+        }
+        // session is stopped, a still plugged vehicle needs a new authorization to resume
+        self.auth_reset(&mut data_set);
+        self.cable_unlock(
+            &mut data_set,
+            matches!(reason, OcppStopReason::UnlockCommand),
+        )?;
+
+        match reason {
+            OcppStopReason::SoftReset => self.ocpp_reset_done(&mut data_set, OcppResetType::Soft),
+            OcppStopReason::HardReset => self.ocpp_reset_done(&mut data_set, OcppResetType::Hard),
+            _ => Ok(()),
+        }
+    }
+
+    fn ocpp_reset_done(
+        &'static self,
+        data_set: &mut MutexGuard<ChargingState>,
+        reset: OcppResetType,
+    ) -> Result<(), AfbError> {
+        if let OcppResetType::Hard = reset {
+            let mut peers = vec![self.iec_api, self.engy_api, self.auth_api];
+            if let Some(slac_api) = self.slac_api {
+                peers.push(slac_api);
+            }
+            for peer in peers {
+                afb_log_msg!(Notice, self.event, "ocpp hard reset request api:{}", peer);
+                AfbSubCall::call_async(
+                    self.apiv4,
+                    peer,
+                    "reset",
+                    AFB_NO_DATA,
+                    ignore_rsp_cb,
+                    IgnoreRspCtx {},
+                )?;
+            }
+        }
+
+        // re-initialise manager state, late auth responses are dropped, availability survives reset
+        let auth_seq = data_set.auth_seq.wrapping_add(1);
+        let availability = match data_set.availability {
+            AvailabilityState::Operative => AvailabilityState::Operative,
+            _ => AvailabilityState::Inoperative,
+        };
+        // plug and phase relay are not touched by reset, lockout, thermal state and site limits survive it
+        let phases = data_set.phases;
+        let plugged = data_set.plugged;
+        let relay = data_set.relay.clone();
+        let thermal = data_set.thermal.clone();
+        let ocpp_limit = data_set.ocpp_limit;
+        let limits = ImaxLimits {
+            contract: None,
+            fault: None,
+            ..data_set.limits.clone()
+        };
+        **data_set = ChargingState::default();
+        data_set.connector_id = self.connector_id;
+        data_set.phases = phases;
+        data_set.plugged = plugged;
+        data_set.ocpp_limit = ocpp_limit;
+        data_set.limits = limits;
+        if thermal.cutoff {
+            data_set.fault = Some(OcppErrorCode::HighTemperature);
+        }
+        data_set.thermal = thermal;
+        if relay.lockout {
+            data_set.fault = Some(OcppErrorCode::PowerSwitchFailure);
+            data_set.limits.fault = Some(0);
+            data_set.relay = relay;
+        }
+        data_set.auth_seq = auth_seq;
+        if let AvailabilityState::Inoperative = availability {
+            self.availability_apply(data_set, availability)?;
+        }
+
+        // peers were reset, imax is pushed even when the arbiter result did not change
+        self.limit_update(data_set)?;
+        AfbSubCall::call_async(
+            self.apiv4,
+            self.iec_api,
+            "imax",
+            data_set.imax,
+            ignore_rsp_cb,
+            IgnoreRspCtx {},
+        )?;
+        self.event.push(ChargingMsg::State((**data_set).clone()));
+
+        if let Some(ocpp_api) = self.ocpp_api {
+            AfbSubCall::call_async(
+                self.apiv4,
+                ocpp_api,
+                "reset-done",
+                reset,
+                ignore_rsp_cb,
+                IgnoreRspCtx {},
+            )?;
+        }
+        self.ocpp_resync(data_set)?;
+        afb_log_msg!(Notice, self.event, "ocpp {:?} reset done", reset);
+        Ok(())
+    }
+
+    // CSMS TriggerMessage, data is re-sent from current session state
+    pub(crate) fn ocpp_trigger(
+        &'static self,
+        data_set: &mut MutexGuard<ChargingState>,
+        request: OcppTriggerMessage,
+    ) -> Result<(), AfbError> {
+        match request {
+            OcppTriggerMessage::StatusNotification => self.ocpp_notify(ocpp_status(data_set)),
+            OcppTriggerMessage::MeterValues => self.ocpp_meter_read(),
+            _ => {
+                // boot/heartbeat/diagnostics/firmware are handled by ocpp binding itself
+                afb_log_msg!(Debug, self.event, "ocpp trigger:{:?} ignored", request);
+                Ok(())
+            }
+        }
+    }
+
+    // energy is read from meter, answer is forwarded as ocpp MeterValues
+    fn ocpp_meter_read(&'static self) -> Result<(), AfbError> {
+        AfbSubCall::call_async(
+            self.apiv4,
+            self.engy_api,
+            "energy",
+            EnergyAction::READ,
+            ocpp_meter_values_cb,
+            MeterValuesCtx { mgr: self },
+        )?;
+        Ok(())
+    }
+
+    fn ocpp_meter_values(&self, meter: &MeterDataSet) -> Result<(), AfbError> {
+        if let Some(ocpp_api) = self.ocpp_api {
+            AfbSubCall::call_async(
+                self.apiv4,
+                ocpp_api,
+                "meter-values",
+                Ocpp16MeterValues {
+                    connector_id: self.connector_id,
+                    meter: meter.clone(),
+                },
+                ignore_rsp_cb,
+                IgnoreRspCtx {},
+            )?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn ocpp_data_transfer(
+        &'static self,
+        request: &OcppDataTransfer,
+    ) -> Result<(), AfbError> {
+        let response = match &self.data_transfer {
+            Some((vendor_id, handler)) if *vendor_id == request.vendor_id => {
+                match handler.transfer(self, request) {
+                    Ok(response) => response,
+                    Err(error) => {
+                        afb_log_msg!(
+                            Warning,
+                            self.event,
+                            "ocpp data transfer rejected:{}",
+                            error.get_info()
+                        );
+                        OcppDataTransferRsp {
+                            status: OcppDataTransferStatus::Rejected,
+                            data: None,
+                        }
+                    }
+                }
+            }
+            _ => OcppDataTransferRsp {
+                status: OcppDataTransferStatus::UnknownVendorId,
+                data: None,
+            },
+        };
+
+        if let Some(ocpp_api) = self.ocpp_api {
+            AfbSubCall::call_async(
+                self.apiv4,
+                ocpp_api,
+                "datatransfer-done",
+                response,
+                ignore_rsp_cb,
+                IgnoreRspCtx {},
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,173 @@
+/*
+ * Copyright (C) 2015-2022 IoT.bzh Company
+ * Author: Fulup Ar Foll <fulup@iot.bzh>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *   http://www.apache.org/licenses/LICENSE-2.0
+ *
+ */
+
+use crate::prelude::*;
+use afbv4::prelude::*;
+use std::sync::MutexGuard;
+use std::time::Instant;
+use typesv4::prelude::*;
+
+// iec61851 minimum charging current (A) and nominal phase voltage (V)
+pub(crate) const PHASE_IMIN: u32 = 6;
+pub(crate) const PHASE_VOLTAGE: u32 = 230;
+
+struct PhaseCtx {
+    mgr: &'static ManagerHandle,
+    phases: u32,
+}
+
+fn phase_pause_cb(_timer: &AfbTimer, _decount: u32, ctx: &AfbCtxData) -> Result<(), AfbError> {
+    let ctx = ctx.get_ref::<PhaseCtx>()?;
+    ctx.mgr.phase_switch_done(ctx.phases)
+}
+
+struct PhaseRelayCtx {
+    mgr: &'static ManagerHandle,
+    phases: u32,
+    resume: bool,
+}
+
+fn phase_relay_cb(_api: &AfbApi, _args: &AfbRqtData, ctx: &AfbCtxData) -> Result<(), AfbError> {
+    let ctx = ctx.get_ref::<PhaseRelayCtx>()?;
+    let mut data_set = ctx.mgr.get_state()?;
+    ctx.mgr
+        .phase_relay_done(&mut data_set, Some(ctx.phases), ctx.resume)
+}
+
+impl ManagerHandle {
+    // 3p only when available power keeps every phase above minimum current
+    pub(crate) fn phase_select(
+        &'static self,
+        data_set: &mut MutexGuard<ChargingState>,
+        available: i32,
+    ) -> Result<(), AfbError> {
+        let config = match &self.phase {
+            Some(value) => value,
+            None => return Ok(()),
+        };
+        if data_set.phase_pending || !matches!(data_set.iso, IsoState::Iec | IsoState::Unset) {
+            return Ok(());
+        }
+
+        let phases = if available >= (3 * PHASE_IMIN * PHASE_VOLTAGE) as i32 {
+            3
+        } else {
+            1
+        };
+        if phases == data_set.phases {
+            return Ok(());
+        }
+
+        // avoid relay chattering when available power oscillates around threshold
+        if let Some(last) = data_set.phase_switch {
+            if last.elapsed().as_millis() < config.delay as u128 {
+                return Ok(());
+            }
+        }
+        self.phase_switch(data_set, phases)
+    }
+
+    // stop power (EV back to state B), wait mandatory pause, switch relay then resume
+    fn phase_switch(
+        &'static self,
+        data_set: &mut MutexGuard<ChargingState>,
+        phases: u32,
+    ) -> Result<(), AfbError> {
+        let config = match &self.phase {
+            Some(value) => value,
+            None => return Ok(()),
+        };
+        afb_log_msg!(
+            Notice,
+            self.event,
+            "phase switch {}p -> {}p",
+            data_set.phases,
+            phases
+        );
+        data_set.phase_pending = true;
+        data_set.phase_switch = Some(Instant::now());
+
+        if !matches!(data_set.power, PowerRequest::Charging(_)) {
+            return self.phase_relay(data_set, phases, false);
+        }
+
+        self.relay_open(data_set)?;
+        AfbTimer::new("phase-pause")
+            .set_period(config.pause)
+            .set_decount(1)
+            .set_callback(phase_pause_cb)
+            .set_context(PhaseCtx { mgr: self, phases })
+            .start()?;
+        Ok(())
+    }
+
+    fn phase_switch_done(&'static self, phases: u32) -> Result<(), AfbError> {
+        let mut data_set = self.get_state()?;
+        self.phase_relay(&mut data_set, phases, true)
+    }
+
+    // relay switch is confirmed asynchronously, pending switch ends either way
+    fn phase_relay(
+        &'static self,
+        data_set: &mut MutexGuard<ChargingState>,
+        phases: u32,
+        resume: bool,
+    ) -> Result<(), AfbError> {
+        let config = match &self.phase {
+            Some(value) => value,
+            None => return self.phase_relay_done(data_set, Some(phases), resume),
+        };
+        let status = AfbSubCall::call_async(
+            self.apiv4,
+            config.api,
+            config.verb,
+            phases,
+            phase_relay_cb,
+            PhaseRelayCtx {
+                mgr: self,
+                phases,
+                resume,
+            },
+        );
+        if let Err(error) = status {
+            afb_log_msg!(
+                Error,
+                self.event,
+                "phase relay {}p switch fail:{}",
+                phases,
+                error.get_info()
+            );
+            return self.phase_relay_done(data_set, None, resume);
+        }
+        Ok(())
+    }
+
+    // phases is None when the relay was not switched, session resumes on previous phases
+    fn phase_relay_done(
+        &'static self,
+        data_set: &mut MutexGuard<ChargingState>,
+        phases: Option<u32>,
+        resume: bool,
+    ) -> Result<(), AfbError> {
+        data_set.phase_pending = false;
+        if let Some(phases) = phases {
+            data_set.phases = phases;
+            self.event.push(ChargingMsg::Phases(phases));
+        }
+
+        // resume only when session is still authorized and vehicle connected
+        if resume && session_active(data_set) {
+            self.power_close(data_set)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,71 @@
+/*
+ * Copyright (C) 2015-2022 IoT.bzh Company
+ * Author: Fulup Ar Foll <fulup@iot.bzh>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *   http://www.apache.org/licenses/LICENSE-2.0
+ *
+ */
+
+use crate::prelude::*;
+use afbv4::prelude::*;
+use typesv4::prelude::*;
+
+impl ManagerHandle {
+    // Plug&Charge contract from iso stack (pnc-contract verb), replaces idp-login for this session
+    pub fn pnc_authorize(&'static self, pnc: &PncContract) -> Result<AuthMsg, AfbError> {
+        let mut data_set = self.get_state()?;
+        if let AvailabilityState::Inoperative = data_set.availability {
+            return afb_error!("charger-inoperative", "authorization refused");
+        }
+        if data_set.relay.lockout {
+            return afb_error!("charger-relay-lockout", "authorization refused");
+        }
+
+        afb_log_msg!(
+            Notice,
+            self.event,
+            "Requesting pnc-auth emaid:{}",
+            pnc.emaid
+        );
+        // contract wins over any idp-login in flight, late rfid answers are dropped
+        self.auth_reset(&mut data_set);
+        let seq = self.auth_pending(&mut data_set)?;
+
+        let validator = match &self.contract_validator {
+            Some(value) => value,
+            None => {
+                self.auth_reject(&mut data_set, "pnc trust store not configured")?;
+                return Ok(data_set.auth);
+            }
+        };
+
+        if let Err(error) = validator.validate(pnc) {
+            let info = format!("emaid:{} invalid contract {}", pnc.emaid, error.get_info());
+            self.auth_reject(&mut data_set, &info)?;
+            return Ok(data_set.auth);
+        }
+
+        // without ocpp the contract must be in local authorization list
+        let ocpp_check = self.ocpp_api.is_some();
+        if !ocpp_check && !validator.is_listed(&pnc.emaid) {
+            let info = format!("emaid:{} not in local list", pnc.emaid);
+            self.auth_reject(&mut data_set, &info)?;
+            return Ok(data_set.auth);
+        }
+
+        let contract = AuthState {
+            auth: AuthMsg::Done,
+            tagid: pnc.emaid.clone(),
+            imax: self.contract_imax,
+            pmax: self.contract_pmax,
+            ocpp_check,
+            iso_only: false,
+        };
+        self.auth_contract(data_set, seq, &contract, Some(pnc))?;
+        Ok(AuthMsg::Pending)
+    }
+}
@@ -0,0 +1,238 @@
+/*
+ * Copyright (C) 2015-2022 IoT.bzh Company
+ * Author: Fulup Ar Foll <fulup@iot.bzh>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *   http://www.apache.org/licenses/LICENSE-2.0
+ *
+ */
+
+use crate::prelude::*;
+use afbv4::prelude::*;
+use std::sync::MutexGuard;
+use typesv4::prelude::*;
+
+// relay-status reads retried before an unreadable register is handled as a relay failure
+const RELAY_READ_RETRY: u32 = 2;
+
+struct RelayCtx {
+    mgr: &'static ManagerHandle,
+    closed: bool,
+    retry: u32,
+}
+
+fn relay_timer_cb(_timer: &AfbTimer, _decount: u32, ctx: &AfbCtxData) -> Result<(), AfbError> {
+    let ctx = ctx.get_ref::<RelayCtx>()?;
+    ctx.mgr.relay_check(ctx.closed, ctx.retry)
+}
+
+fn relay_status_cb(_api: &AfbApi, args: &AfbRqtData, ctx: &AfbCtxData) -> Result<(), AfbError> {
+    let ctx = ctx.get_ref::<RelayCtx>()?;
+
+    match args.get::<u32>(0) {
+        Ok(status) => ctx.mgr.relay_current(ctx.closed, ctx.retry, status),
+        Err(error) => ctx
+            .mgr
+            .relay_read_fail(ctx.closed, ctx.retry, &error.get_info()),
+    }
+}
+
+struct RelayCurrentCtx {
+    mgr: &'static ManagerHandle,
+    closed: bool,
+    retry: u32,
+    status: u32,
+}
+
+fn relay_current_cb(_api: &AfbApi, args: &AfbRqtData, ctx: &AfbCtxData) -> Result<(), AfbError> {
+    let ctx = ctx.get_ref::<RelayCurrentCtx>()?;
+
+    match args.get::<&MeterDataSet>(0) {
+        Ok(meter) => {
+            let current = meter.l1.max(meter.l2).max(meter.l3);
+            ctx.mgr.relay_verify(ctx.closed, ctx.status, current)
+        }
+        Err(error) => ctx
+            .mgr
+            .relay_read_fail(ctx.closed, ctx.retry, &error.get_info()),
+    }
+}
+
+impl ManagerHandle {
+    // firmware relay report is verified against relay-status register once contacts settled
+    pub(crate) fn relay_supervise(
+        &'static self,
+        data_set: &mut MutexGuard<ChargingState>,
+        closed: bool,
+    ) -> Result<(), AfbError> {
+        let config = match &self.relay {
+            Some(value) => value,
+            None => return Ok(()),
+        };
+
+        data_set.relay.commanded = Some(closed);
+        self.relay_timer(config, closed, 0)
+    }
+
+    // relay opened by chmgr itself, verified without waiting for firmware report
+    pub(crate) fn relay_open(
+        &'static self,
+        data_set: &mut MutexGuard<ChargingState>,
+    ) -> Result<(), AfbError> {
+        AfbSubCall::call_async(
+            self.apiv4,
+            self.iec_api,
+            "power",
+            false,
+            ignore_rsp_cb,
+            IgnoreRspCtx {},
+        )?;
+        self.relay_supervise(data_set, false)
+    }
+
+    fn relay_timer(
+        &'static self,
+        config: &RelayConfig,
+        closed: bool,
+        retry: u32,
+    ) -> Result<(), AfbError> {
+        AfbTimer::new("relay-check")
+            .set_period(config.delay)
+            .set_decount(1)
+            .set_callback(relay_timer_cb)
+            .set_context(RelayCtx {
+                mgr: self,
+                closed,
+                retry,
+            })
+            .start()?;
+        Ok(())
+    }
+
+    fn relay_check(&'static self, closed: bool, retry: u32) -> Result<(), AfbError> {
+        let config = match &self.relay {
+            Some(value) => value,
+            None => return Ok(()),
+        };
+
+        AfbSubCall::call_async(
+            self.apiv4,
+            config.api,
+            config.status_verb,
+            AFB_NO_DATA,
+            relay_status_cb,
+            RelayCtx {
+                mgr: self,
+                closed,
+                retry,
+            },
+        )?;
+        Ok(())
+    }
+
+    // relay state or current cannot be read, retried then handled as a failure
+    fn relay_read_fail(
+        &'static self,
+        closed: bool,
+        retry: u32,
+        info: &str,
+    ) -> Result<(), AfbError> {
+        let config = match &self.relay {
+            Some(value) => value,
+            None => return Ok(()),
+        };
+        let mut data_set = self.get_state()?;
+        if data_set.relay.commanded != Some(closed) {
+            return Ok(());
+        }
+        afb_log_msg!(
+            Warning,
+            self.event,
+            "relay-status read fail:{} retry:{}",
+            info,
+            retry
+        );
+        if retry < RELAY_READ_RETRY {
+            return self.relay_timer(config, closed, retry + 1);
+        }
+        let info = format!("relay-status unreadable:{}", info);
+        self.relay_fault(&mut data_set, &info)
+    }
+
+    // register is checked together with measured current, weld leaves the register open
+    fn relay_current(&'static self, closed: bool, retry: u32, status: u32) -> Result<(), AfbError> {
+        AfbSubCall::call_async(
+            self.apiv4,
+            self.engy_api,
+            "current",
+            EnergyAction::READ,
+            relay_current_cb,
+            RelayCurrentCtx {
+                mgr: self,
+                closed,
+                retry,
+                status,
+            },
+        )?;
+        Ok(())
+    }
+
+    // an open relay must not let current flow, whatever the register says
+    fn relay_verify(&self, closed: bool, status: u32, current: i32) -> Result<(), AfbError> {
+        let config = match &self.relay {
+            Some(value) => value,
+            None => return Ok(()),
+        };
+
+        let mut data_set = self.get_state()?;
+        if data_set.relay.commanded != Some(closed) {
+            // a newer command is under verification
+            return Ok(());
+        }
+        let measured = status & config.mask != 0;
+        data_set.relay.measured = Some(measured);
+        data_set.relay.current = Some(current);
+
+        if measured != closed {
+            let info = format!("relay commanded:{} measured:{}", closed, measured);
+            return self.relay_fault(&mut data_set, &info);
+        }
+        if !closed && current > config.current {
+            let info = format!("relay open with current:{}mA", current);
+            return self.relay_fault(&mut data_set, &info);
+        }
+        Ok(())
+    }
+
+    // welded or stuck contactor locks the connector out until reboot
+    pub(crate) fn relay_fault(
+        &self,
+        data_set: &mut MutexGuard<ChargingState>,
+        info: &str,
+    ) -> Result<(), AfbError> {
+        afb_log_msg!(
+            Error,
+            self.event,
+            "relay failure {}, connector lockout",
+            info
+        );
+        data_set.relay.lockout = true;
+        data_set.relay.error = Some(ErrorState::ErrRelay);
+        data_set.fault = Some(OcppErrorCode::PowerSwitchFailure);
+        AfbSubCall::call_async(
+            self.apiv4,
+            self.iec_api,
+            "power",
+            false,
+            ignore_rsp_cb,
+            IgnoreRspCtx {},
+        )?;
+        self.v2g_stop(data_set, "relay-failure")?;
+        self.limit_set(data_set, LimitSource::Fault, Some(0))?;
+        self.event.push(ChargingMsg::Relay(data_set.relay.clone()));
+        self.ocpp_notify(OcppChargerStatus::Error(OcppErrorCode::PowerSwitchFailure))
+    }
+}
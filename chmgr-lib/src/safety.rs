@@ -0,0 +1,149 @@
+/*
+ * Copyright (C) 2015-2022 IoT.bzh Company
+ * Author: Fulup Ar Foll <fulup@iot.bzh>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *   http://www.apache.org/licenses/LICENSE-2.0
+ *
+ */
+
+use crate::ocpp::*;
+use crate::prelude::*;
+use afbv4::prelude::*;
+use std::sync::MutexGuard;
+use typesv4::prelude::*;
+
+struct SafetyTestCtx {
+    mgr: &'static ManagerHandle,
+}
+
+fn safety_test_cb(_api: &AfbApi, args: &AfbRqtData, ctx: &AfbCtxData) -> Result<(), AfbError> {
+    let ctx = ctx.get_ref::<SafetyTestCtx>()?;
+
+    match args.get::<&SafetyReport>(0) {
+        Ok(report) => ctx.mgr.safety_done(&report.results),
+        Err(error) => ctx.mgr.safety_fail(&error.get_info()),
+    }
+}
+
+// iec firmware error and ocpp error code reported for a failed test
+fn safety_error(test: SafetyTest) -> (ErrorState, OcppErrorCode) {
+    match test {
+        SafetyTest::Rcd => (ErrorState::ErrRdc, OcppErrorCode::GroundFailure),
+        SafetyTest::RelayWeld => (ErrorState::ErrRelay, OcppErrorCode::PowerSwitchFailure),
+        SafetyTest::Pe => (ErrorState::ErrE, OcppErrorCode::GroundFailure),
+    }
+}
+
+impl ManagerHandle {
+    // configured self-tests run by iec firmware, relay stays open until they pass
+    pub(crate) fn safety_start(
+        &'static self,
+        data_set: &mut MutexGuard<ChargingState>,
+    ) -> Result<(), AfbError> {
+        let config = match &self.safety {
+            Some(value) => value,
+            None => return Ok(()),
+        };
+        if data_set.safety.running {
+            return Ok(());
+        }
+
+        afb_log_msg!(Notice, self.event, "safety self-test:{:?}", config.tests);
+        data_set.safety.running = true;
+        AfbSubCall::call_async(
+            self.apiv4,
+            self.iec_api,
+            "self-test",
+            SafetyRequest {
+                tests: config.tests.clone(),
+            },
+            safety_test_cb,
+            SafetyTestCtx { mgr: self },
+        )?;
+        Ok(())
+    }
+
+    // periodic test from safety timer, only while the connector is free
+    pub fn safety_tic(&'static self) -> Result<(), AfbError> {
+        let mut data_set = self.get_state()?;
+        if is_plugged(&data_set) {
+            return Ok(());
+        }
+        self.safety_start(&mut data_set)
+    }
+
+    fn safety_done(&'static self, results: &[SafetyResult]) -> Result<(), AfbError> {
+        let mut data_set = self.get_state()?;
+        data_set.safety.results = results.to_vec();
+        match results.iter().find(|result| !result.passed) {
+            Some(result) => {
+                let (error, code) = safety_error(result.test);
+                afb_log_msg!(
+                    Error,
+                    self.event,
+                    "safety test:{:?} failed info:{:?}",
+                    result.test,
+                    result.info
+                );
+                self.safety_result(&mut data_set, Some((Some(error), code)))?;
+                // a welded relay locks the connector out like a supervision failure
+                if let SafetyTest::RelayWeld = result.test {
+                    self.relay_fault(&mut data_set, "weld self-test")?;
+                }
+                Ok(())
+            }
+            None => self.safety_result(&mut data_set, None),
+        }
+    }
+
+    // no answer from iec firmware is handled as a failed test
+    fn safety_fail(&'static self, info: &str) -> Result<(), AfbError> {
+        let mut data_set = self.get_state()?;
+        afb_log_msg!(Error, self.event, "safety self-test error:{}", info);
+        data_set.safety.results = Vec::new();
+        self.safety_result(&mut data_set, Some((None, OcppErrorCode::InternalError)))
+    }
+
+    fn safety_result(
+        &'static self,
+        data_set: &mut MutexGuard<ChargingState>,
+        failure: Option<(Option<ErrorState>, OcppErrorCode)>,
+    ) -> Result<(), AfbError> {
+        // drop the fault raised by a previous failed test
+        if let Some(previous) = data_set.safety.fault.clone() {
+            fault_clear(data_set, previous);
+        }
+
+        let diag = &mut data_set.safety;
+        diag.running = false;
+        diag.last = Some(get_timestamp());
+        diag.passed = Some(failure.is_none());
+        diag.error = failure.as_ref().and_then(|(error, _)| *error);
+        diag.fault = failure.as_ref().map(|(_, code)| code.clone());
+
+        let diag = data_set.safety.clone();
+        if is_plugged(data_set) {
+            data_set.session.safety = Some(diag.clone());
+        }
+        self.event.push(ChargingMsg::Safety(diag));
+
+        match failure {
+            Some((_, code)) => {
+                fault_set(data_set, code);
+                self.ocpp_notify(ocpp_status(data_set))
+            }
+            None => {
+                afb_log_msg!(Notice, self.event, "safety self-test passed");
+                self.ocpp_notify(ocpp_status(data_set))?;
+                if session_active(data_set) {
+                    self.power_close(data_set)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
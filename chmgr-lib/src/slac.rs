@@ -0,0 +1,117 @@
+/*
+ * Copyright (C) 2015-2022 IoT.bzh Company
+ * Author: Fulup Ar Foll <fulup@iot.bzh>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *   http://www.apache.org/licenses/LICENSE-2.0
+ *
+ */
+
+use crate::prelude::*;
+use afbv4::prelude::*;
+use std::sync::MutexGuard;
+use std::time::Instant;
+use typesv4::prelude::*;
+
+// close running attempt and account its duration
+fn slac_attempt_done(data_set: &mut ChargingState) {
+    let diag = &mut data_set.session.slac;
+    if let Some(start) = diag.start.take() {
+        let duration = start.elapsed().as_millis() as u32;
+        diag.last_duration = duration;
+        diag.total_duration += duration;
+    }
+}
+
+impl ManagerHandle {
+    pub fn slac(&'static self, api: AfbApiV4, msg: &SlacStatus) -> Result<(), AfbError> {
+        // Inform the firmware that SLAC failed
+        match msg {
+            SlacStatus::TIMEOUT => {
+                AfbSubCall::call_sync(api, self.iec_api, "slac", *msg)?;
+            }
+            _ => {}
+        }
+
+        let mut state = self.get_state()?;
+        state.slac = *msg;
+        self.event.push(ChargingMsg::Slac(*msg));
+
+        let iso_state = match msg {
+            SlacStatus::MATCHING => {
+                // display shows matching phase until MATCHED/UNMATCHED
+                if state.session.slac.start.is_none() {
+                    state.session.slac.start = Some(Instant::now());
+                    state.session.slac.attempts += 1;
+                }
+                return Ok(());
+            }
+            SlacStatus::MATCHED => {
+                slac_attempt_done(&mut state);
+                // iso stack pulls charge parameters from iso-param verb
+                state.iso_param = None;
+                IsoState::Iso3
+            }
+            SlacStatus::UNMATCHED => {
+                slac_attempt_done(&mut state);
+                state.session.slac.unmatched += 1;
+                if let (true, Some(slac_api)) = (
+                    state.session.slac.unmatched <= self.slac_retry,
+                    self.slac_api,
+                ) {
+                    afb_log_msg!(
+                        Notice,
+                        self.event,
+                        "slac unmatched retry:{}/{}",
+                        state.session.slac.unmatched,
+                        self.slac_retry
+                    );
+                    AfbSubCall::call_async(
+                        self.apiv4,
+                        slac_api,
+                        "start",
+                        AFB_NO_DATA,
+                        ignore_rsp_cb,
+                        IgnoreRspCtx {},
+                    )?;
+                    return Ok(());
+                }
+                // retries exhausted, inform the firmware as for a timeout
+                AfbSubCall::call_async(
+                    self.apiv4,
+                    self.iec_api,
+                    "slac",
+                    SlacStatus::TIMEOUT,
+                    ignore_rsp_cb,
+                    IgnoreRspCtx {},
+                )?;
+                return self.iec_fallback(&mut state, FallbackReason::SlacTimeout);
+            }
+            SlacStatus::TIMEOUT => {
+                slac_attempt_done(&mut state);
+                state.session.slac.timeout += 1;
+                return self.iec_fallback(&mut state, FallbackReason::SlacTimeout);
+            }
+
+            SlacStatus::WAITING | SlacStatus::JOINING | SlacStatus::IDLE => {
+                return Ok(());
+            }
+        };
+        state.iso = iso_state;
+        self.event.push(ChargingMsg::Iso(iso_state));
+        Ok(())
+    }
+
+    // vehicle left, next plug-in restarts SLAC from IDLE
+    pub(crate) fn slac_reset(&self, data_set: &mut MutexGuard<ChargingState>) {
+        slac_attempt_done(data_set);
+        if let SlacStatus::IDLE = data_set.slac {
+            return;
+        }
+        data_set.slac = SlacStatus::IDLE;
+        self.event.push(ChargingMsg::Slac(data_set.slac));
+    }
+}
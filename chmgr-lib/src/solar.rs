@@ -0,0 +1,136 @@
+/*
+ * Copyright (C) 2015-2022 IoT.bzh Company
+ * Author: Fulup Ar Foll <fulup@iot.bzh>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *   http://www.apache.org/licenses/LICENSE-2.0
+ *
+ */
+
+use crate::phase::*;
+use crate::prelude::*;
+use afbv4::prelude::*;
+use std::sync::MutexGuard;
+use std::time::Instant;
+use typesv4::prelude::*;
+
+// session is over, next one starts with contract current
+pub(crate) fn solar_reset(data_set: &mut ChargingState) {
+    data_set.solar = SolarMode::Off;
+    data_set.solar_imax = None;
+    data_set.solar_paused = false;
+    data_set.solar_avg = None;
+    data_set.solar_low = None;
+}
+
+impl ManagerHandle {
+    // solar-mode verb, kept as a shortcut to the matching charging mode
+    pub fn solar_mode(&'static self, mode: SolarMode) -> Result<(), AfbError> {
+        let mode = match mode {
+            SolarMode::Off => ChargingMode::Fast,
+            SolarMode::Solar => ChargingMode::Solar,
+            SolarMode::Hybrid => ChargingMode::Eco,
+        };
+        self.charging_mode(mode)
+    }
+
+    pub(crate) fn solar_apply(
+        &'static self,
+        data_set: &mut MutexGuard<ChargingState>,
+        mode: SolarMode,
+    ) -> Result<(), AfbError> {
+        afb_log_msg!(Notice, self.event, "solar mode:{:?}", mode);
+        let paused = data_set.solar_paused;
+        solar_reset(data_set);
+        data_set.solar = mode;
+        if paused {
+            self.solar_resume(data_set)?;
+        }
+        self.event.push(ChargingMsg::Solar(mode));
+        self.limit_update(data_set)
+    }
+
+    // grid power (W) from energy/linky api, positive when importing
+    pub fn solar_grid(&'static self, grid: i32) -> Result<(), AfbError> {
+        let config = match &self.solar {
+            Some(value) => value,
+            None => return Ok(()),
+        };
+        let mut data_set = self.get_state()?;
+        if let SolarMode::Off = data_set.solar {
+            return Ok(());
+        }
+
+        // our own consumption is part of grid import, measured current (mA) when available
+        let charging = match (data_set.power, data_set.meter.current) {
+            (PowerRequest::Charging(_), Some(current)) => {
+                current.max(0) * (data_set.phases * PHASE_VOLTAGE) as i32 / 1000
+            }
+            (PowerRequest::Charging(_), None) => {
+                (data_set.imax * data_set.phases * PHASE_VOLTAGE) as i32
+            }
+            _ => 0,
+        };
+        let surplus = charging - grid;
+        let average = match data_set.solar_avg {
+            Some(average) => average + (surplus - average) * config.smoothing as i32 / 100,
+            None => surplus,
+        };
+        data_set.solar_avg = Some(average);
+        self.phase_select(&mut data_set, average)?;
+
+        let imax = (average.max(0) as u32) / (data_set.phases * PHASE_VOLTAGE);
+        let imax = match data_set.solar {
+            SolarMode::Hybrid => imax.max(config.hybrid_imin),
+            _ => imax,
+        };
+
+        if imax < PHASE_IMIN {
+            let low = *data_set.solar_low.get_or_insert_with(Instant::now);
+            if !data_set.solar_paused && low.elapsed().as_millis() >= config.pause_delay as u128 {
+                self.solar_pause(&mut data_set)?;
+            }
+            return Ok(());
+        }
+
+        data_set.solar_low = None;
+        if data_set.solar_paused {
+            data_set.solar_imax = Some(imax);
+            return self.solar_resume(&mut data_set);
+        }
+        if data_set.solar_imax != Some(imax) {
+            data_set.solar_imax = Some(imax);
+            self.limit_update(&mut data_set)?;
+        }
+        Ok(())
+    }
+
+    fn solar_pause(
+        &'static self,
+        data_set: &mut MutexGuard<ChargingState>,
+    ) -> Result<(), AfbError> {
+        afb_log_msg!(Notice, self.event, "solar surplus too low, pause charge");
+        data_set.solar_paused = true;
+        data_set.solar_imax = Some(0);
+        if let PowerRequest::Charging(_) = data_set.power {
+            self.relay_open(data_set)?;
+        }
+        self.limit_update(data_set)
+    }
+
+    fn solar_resume(
+        &'static self,
+        data_set: &mut MutexGuard<ChargingState>,
+    ) -> Result<(), AfbError> {
+        afb_log_msg!(Notice, self.event, "solar charge resume");
+        data_set.solar_paused = false;
+        self.limit_update(data_set)?;
+        if session_active(data_set) {
+            self.power_close(data_set)?;
+        }
+        Ok(())
+    }
+}
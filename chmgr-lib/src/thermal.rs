@@ -0,0 +1,135 @@
+/*
+ * Copyright (C) 2015-2022 IoT.bzh Company
+ * Author: Fulup Ar Foll <fulup@iot.bzh>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *   http://www.apache.org/licenses/LICENSE-2.0
+ *
+ */
+
+use crate::ocpp::*;
+use crate::prelude::*;
+use afbv4::prelude::*;
+use std::sync::MutexGuard;
+use typesv4::prelude::*;
+
+// linear interpolation between curve points, no derating under first point
+fn thermal_derate(curve: &[ThermalPoint], temperature: i32) -> Option<u32> {
+    let first = curve.first()?;
+    if temperature < first.temperature {
+        return None;
+    }
+    for pair in curve.windows(2) {
+        let (low, high) = (&pair[0], &pair[1]);
+        if temperature < high.temperature {
+            let span = (high.temperature - low.temperature) as i64;
+            let delta = (temperature - low.temperature) as i64;
+            let imax = low.imax as i64 + (high.imax as i64 - low.imax as i64) * delta / span;
+            return Some(imax as u32);
+        }
+    }
+    curve.last().map(|point| point.imax)
+}
+
+impl ManagerHandle {
+    // sensor temperature (°C), samples without connector id apply to every connector
+    pub fn thermal_sample(&'static self, sample: &ThermalSample) -> Result<(), AfbError> {
+        let config = match &self.thermal {
+            Some(value) => value,
+            None => return Ok(()),
+        };
+        if let Some(connector_id) = sample.connector {
+            if connector_id != self.connector_id {
+                return Ok(());
+            }
+        }
+
+        let mut data_set = self.get_state()?;
+        match sample.sensor {
+            ThermalSensor::Connector => data_set.thermal.connector = Some(sample.temperature),
+            ThermalSensor::Relay => data_set.thermal.relay = Some(sample.temperature),
+            ThermalSensor::Enclosure => data_set.thermal.enclosure = Some(sample.temperature),
+        }
+        let thermal = &data_set.thermal;
+        let hottest = match [thermal.connector, thermal.relay, thermal.enclosure]
+            .into_iter()
+            .flatten()
+            .max()
+        {
+            Some(value) => value,
+            None => return Ok(()),
+        };
+
+        // once tripped, wait for temperature to go under cutoff minus hysteresis
+        let resume = data_set.thermal.cutoff;
+        if resume {
+            if hottest > config.cutoff - config.hysteresis {
+                return Ok(());
+            }
+            self.thermal_resume(&mut data_set, hottest)?;
+        } else if hottest >= config.cutoff {
+            return self.thermal_cutoff(&mut data_set, hottest);
+        }
+
+        let derate = thermal_derate(&config.curve, hottest);
+        if data_set.limits.thermal != derate {
+            afb_log_msg!(
+                Notice,
+                self.event,
+                "thermal derating temperature:{} imax:{:?}",
+                hottest,
+                derate
+            );
+            self.limit_set(&mut data_set, LimitSource::Thermal, derate)?;
+        }
+
+        if resume && session_active(&data_set) {
+            self.power_close(&mut data_set)?;
+        }
+        Ok(())
+    }
+
+    fn thermal_cutoff(
+        &'static self,
+        data_set: &mut MutexGuard<ChargingState>,
+        temperature: i32,
+    ) -> Result<(), AfbError> {
+        afb_log_msg!(
+            Error,
+            self.event,
+            "temperature:{} above cutoff, stop charge",
+            temperature
+        );
+        fault_set(data_set, OcppErrorCode::HighTemperature);
+        data_set.thermal.cutoff = true;
+        if let PowerRequest::Charging(_) = data_set.power {
+            self.relay_open(data_set)?;
+        }
+        self.v2g_stop(data_set, "high-temperature")?;
+        self.limit_set(data_set, LimitSource::Thermal, Some(0))?;
+        self.event
+            .push(ChargingMsg::Thermal(data_set.thermal.clone()));
+        self.ocpp_notify(ocpp_status(data_set))
+    }
+
+    fn thermal_resume(
+        &self,
+        data_set: &mut MutexGuard<ChargingState>,
+        temperature: i32,
+    ) -> Result<(), AfbError> {
+        afb_log_msg!(
+            Notice,
+            self.event,
+            "temperature:{} back under cutoff, resume charge",
+            temperature
+        );
+        data_set.thermal.cutoff = false;
+        fault_clear(data_set, OcppErrorCode::HighTemperature);
+        self.event
+            .push(ChargingMsg::Thermal(data_set.thermal.clone()));
+        self.ocpp_notify(ocpp_status(data_set))
+    }
+}
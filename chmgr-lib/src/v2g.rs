@@ -0,0 +1,119 @@
+/*
+ * Copyright (C) 2015-2022 IoT.bzh Company
+ * Author: Fulup Ar Foll <fulup@iot.bzh>
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *   http://www.apache.org/licenses/LICENSE-2.0
+ *
+ */
+
+use crate::phase::*;
+use crate::prelude::*;
+use afbv4::prelude::*;
+use std::sync::MutexGuard;
+use typesv4::prelude::*;
+
+impl ManagerHandle {
+    // setpoint in W from v2g-setpoint verb or energy manager, negative value requests discharge
+    pub fn v2g_setpoint(&self, setpoint: i32) -> Result<i32, AfbError> {
+        let mut data_set = self.get_state()?;
+        let setpoint = self.v2g_clamp(&data_set, setpoint);
+        self.v2g_apply(&mut data_set, setpoint)?;
+        Ok(setpoint)
+    }
+
+    // charge is bounded by session limits, discharge requires an iso-20 session, v2g config,
+    // a known EV SoC above floor and no active fault
+    fn v2g_clamp(&self, data_set: &ChargingState, setpoint: i32) -> i32 {
+        if setpoint >= 0 {
+            let mut pmax = data_set.imax * data_set.phases * PHASE_VOLTAGE;
+            if data_set.pmax > 0 {
+                pmax = pmax.min(data_set.pmax * 1000);
+            }
+            return setpoint.min(pmax as i32);
+        }
+        if !matches!(data_set.iso, IsoState::Iso20 | IsoState::Iso20Discharge) {
+            return 0;
+        }
+
+        let config = match &self.v2g {
+            Some(value) => value,
+            None => return 0,
+        };
+        if data_set.fault.is_some() {
+            return 0;
+        }
+        match data_set.ev.as_ref().and_then(|ev| ev.soc) {
+            Some(soc) if soc > config.soc_min => {}
+            _ => return 0,
+        }
+        // discharge power is bounded by both pmax (kW) and discharge current on active phases
+        let pmax = (config.pmax * 1000).min(config.imax * data_set.phases * PHASE_VOLTAGE);
+        setpoint.max(-(pmax as i32))
+    }
+
+    fn v2g_apply(
+        &self,
+        data_set: &mut MutexGuard<ChargingState>,
+        setpoint: i32,
+    ) -> Result<(), AfbError> {
+        if data_set.setpoint == setpoint {
+            return Ok(());
+        }
+        afb_log_msg!(Notice, self.event, "v2g setpoint:{}W", setpoint);
+        data_set.setpoint = setpoint;
+        self.event.push(ChargingMsg::Setpoint(setpoint));
+        self.iso_renegotiate(data_set)
+    }
+
+    // re-check running discharge after EV SoC update
+    pub(crate) fn v2g_check(
+        &self,
+        data_set: &mut MutexGuard<ChargingState>,
+    ) -> Result<(), AfbError> {
+        let setpoint = self.v2g_clamp(data_set, data_set.setpoint);
+        self.v2g_apply(data_set, setpoint)
+    }
+
+    // any grid fault stops discharge immediately
+    pub(crate) fn v2g_stop(
+        &self,
+        data_set: &mut MutexGuard<ChargingState>,
+        reason: &str,
+    ) -> Result<(), AfbError> {
+        if data_set.setpoint >= 0 {
+            return Ok(());
+        }
+        afb_log_msg!(
+            Warning,
+            self.event,
+            "v2g discharge stopped reason:{}",
+            reason
+        );
+        self.v2g_apply(data_set, 0)
+    }
+
+    pub fn engy_energy(&self, msg: &MeterDataSet) -> Result<(), AfbError> {
+        let mut data_set = self.get_state()?;
+        if let MeterTagSet::Energy = msg.tag {
+            self.meter_energy(&mut data_set, msg.total)?;
+            session_energy(&mut data_set, msg.total);
+        }
+        Ok(())
+    }
+}
+
+// session meter is bidirectional, split its variations into imported/exported energy
+pub(crate) fn session_energy(data_set: &mut ChargingState, total: i32) {
+    let session = &mut data_set.session;
+    let delta = total - session.energy_last;
+    if delta > 0 {
+        session.energy_imported += delta;
+    } else {
+        session.energy_exported -= delta;
+    }
+    session.energy_last = total;
+}